@@ -0,0 +1,80 @@
+//! Build-time benchmarks, run with `cargo bench`.
+
+use std::time::{Duration, Instant};
+
+use cgmath::Vector3;
+use kdtree_ray::{AABB, BuilderConfig, KDTree, Threading};
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::Rng;
+
+/// Function generating a scene of a given number of shapes.
+type Scene = fn(usize) -> Vec<AABB>;
+
+/// Bounding boxes of small random triangles in a cube.
+fn triangles(nb_triangles: usize) -> Vec<AABB> {
+    let mut rng = Rng(0);
+    let scale = (nb_triangles as f32).cbrt();
+    (0..nb_triangles)
+        .map(|_| {
+            let min = rng.vector(scale);
+            let size = rng.vector(1.);
+            AABB::new(min, min + size)
+        })
+        .collect()
+}
+
+/// Axis-aligned quads on a grid of floors.
+fn quads(nb_quads: usize) -> Vec<AABB> {
+    let side = (nb_quads as f32).cbrt().ceil() as usize;
+    (0..nb_quads)
+        .map(|i| {
+            let min = Vector3::new(
+                (i % side) as f32,
+                ((i / side) % side) as f32,
+                (i / side / side) as f32,
+            );
+            AABB::new(min, min + Vector3::new(1., 1., 0.))
+        })
+        .collect()
+}
+
+/// Return the best time of a few builds.
+fn bench(shapes: &[AABB], config: &BuilderConfig) -> Duration {
+    let runs = if shapes.len() >= 1_000_000 { 1 } else { 3 };
+    (0..runs)
+        .map(|_| {
+            let start = Instant::now();
+            let tree = KDTree::build_config(shapes, config);
+            let elapsed = start.elapsed();
+            drop(tree);
+            elapsed
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let configs = [
+        (
+            "sequential",
+            BuilderConfig::default().with_threading(Threading::Sequential),
+        ),
+        ("parallel", BuilderConfig::default()),
+    ];
+    let scenes: [(&str, Scene); 2] = [("triangles", triangles), ("quads", quads)];
+    for (scene_name, scene) in scenes {
+        for nb_shapes in [10_000, 100_000, 1_000_000] {
+            let shapes = scene(nb_shapes);
+            for (config_name, config) in &configs {
+                let elapsed = bench(&shapes, config);
+                println!(
+                    "build {scene_name:>9} {nb_shapes:>9} {config_name:>10}: {:>10.1} ms",
+                    elapsed.as_secs_f64() * 1000.
+                );
+            }
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use crate::Point3;
+use std::sync::Arc;
+
+use crate::Float;
+use crate::dim::{D3, Dim};
 
 /// Axis-aligned bounding box is defined by two positions.
 ///
@@ -10,67 +13,132 @@ use crate::Point3;
 /// snugly fit the entity contained inside.
 ///
 /// ![Gif describing an AABB](https://media.prod.mdn.mozit.cloud/attachments/2015/10/16/11799/57dfaf5508784d6b9c5fe77c0df49a54/rotating_knot.gif)
-#[derive(Clone, Debug)]
-pub struct AABB {
+#[derive(Clone, Debug, PartialEq)]
+pub struct AABB<T: Float = f32, D: Dim = D3> {
     /// Minimum position
-    pub min: Point3,
+    pub min: D::Vector<T>,
     /// Maximum position
-    pub max: Point3,
+    pub max: D::Vector<T>,
 }
 
-impl Default for AABB {
+impl<T: Float, D: Dim> Default for AABB<T, D> {
     fn default() -> Self {
         Self::empty()
     }
 }
 
-impl AABB {
+impl<T: Float, D: Dim> AABB<T, D> {
     /// Create an new AABB from two points.
-    pub fn new(min: Point3, max: Point3) -> Self {
+    pub fn new(min: D::Vector<T>, max: D::Vector<T>) -> Self {
         Self { min, max }
     }
 
     /// Create an empty AABB.
     pub fn empty() -> Self {
-        Self::new(
-            Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
-            Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
-        )
+        Self::new(D::splat(T::infinity()), D::splat(T::neg_infinity()))
+    }
+
+    /// Compute AABB volume (its area in 2D).
+    pub fn volume(&self) -> T {
+        (0..D::N).fold(T::one(), |volume, axis| {
+            volume * (self.max[axis] - self.min[axis])
+        })
     }
 
-    /// Compute AABB volume
-    pub fn volume(&self) -> f32 {
-        (self.max.x - self.min.x) * (self.max.y - self.min.y) * (self.max.z - self.min.z)
+    /// Compute AABB surface (its perimeter in 2D).
+    pub fn surface(&self) -> T {
+        let mut extent = self.max;
+        (0..D::N).for_each(|axis| extent[axis] = self.max[axis] - self.min[axis]);
+        D::surface(&extent)
     }
 
-    /// Compute AABB surface
-    pub fn surface(&self) -> f32 {
-        let dx = self.max.x - self.min.x;
-        let dy = self.max.y - self.min.y;
-        let dz = self.max.z - self.min.z;
-        2.0 * (dx * dy + dx * dz + dy * dz)
+    /// Compute the smallest AABB enclosing every AABB of `bounds`, the space of a tree.
+    pub(crate) fn enclosing(bounds: &[Self]) -> Self {
+        let mut space = Self::empty();
+        for bb in bounds {
+            space.merge(bb);
+        }
+        space
     }
 
     /// Merge another AABB into this one.
     pub fn merge(&mut self, other: &Self) {
-        self.min = Point3::new(
-            self.min.x.min(other.min.x),
-            self.min.y.min(other.min.y),
-            self.min.z.min(other.min.z),
-        );
-        self.max = Point3::new(
-            self.max.x.max(other.max.x),
-            self.max.y.max(other.max.y),
-            self.max.z.max(other.max.z),
-        );
+        for axis in 0..D::N {
+            self.min[axis] = self.min[axis].min(other.min[axis]);
+            self.max[axis] = self.max[axis].max(other.max[axis]);
+        }
+    }
+
+    /// Linearly interpolate between this AABB (`t = 0.`) and another one (`t = 1.`).
+    pub fn lerp(&self, other: &Self, t: T) -> Self {
+        let mut lerp = self.clone();
+        for axis in 0..D::N {
+            lerp.min[axis] = self.min[axis] + (other.min[axis] - self.min[axis]) * t;
+            lerp.max[axis] = self.max[axis] + (other.max[axis] - self.max[axis]) * t;
+        }
+        lerp
+    }
+
+    /// Compute the intersection of this AABB with another one.
+    /// The result is empty if they don't intersect.
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut intersection = self.clone();
+        for axis in 0..D::N {
+            intersection.min[axis] = self.min[axis].max(other.min[axis]);
+            intersection.max[axis] = self.max[axis].min(other.max[axis]);
+        }
+        intersection
+    }
+
+    /// Check if the AABB is empty (its minimum is greater than its maximum on an axis).
+    ///
+    /// **Note**: A flat AABB (with a null volume) isn't empty.
+    pub fn is_empty(&self) -> bool {
+        (0..D::N).any(|axis| self.min[axis] > self.max[axis])
+    }
+
+    /// Check if another AABB is inside this one (borders included).
+    pub fn contains(&self, other: &Self) -> bool {
+        (0..D::N).all(|axis| self.min[axis] <= other.min[axis] && self.max[axis] >= other.max[axis])
+    }
+
+    /// Check if another AABB overlaps this one.
+    ///
+    /// **Note**: Boxes that are only touching each other are not overlapping.
+    pub fn overlaps(&self, other: &Self) -> bool {
+        (0..D::N).all(|axis| self.min[axis] < other.max[axis] && self.max[axis] > other.min[axis])
     }
 }
 
 /// Your shapes needs to implement `Bounded` trait to build a KD-tree around it.
-pub trait Bounded {
+pub trait Bounded<T: Float = f32, D: Dim = D3> {
     /// This function return the **Axis-aligned bounding boxes**
     /// (`AABB`) of the object.
     ///
     /// For more information check [AABB](type.AABB.html).
-    fn bound(&self) -> AABB;
+    fn bound(&self) -> AABB<T, D>;
+}
+
+impl<T: Float, D: Dim> Bounded<T, D> for AABB<T, D> {
+    fn bound(&self) -> AABB<T, D> {
+        self.clone()
+    }
+}
+
+impl<T: Float, D: Dim, B: Bounded<T, D> + ?Sized> Bounded<T, D> for &B {
+    fn bound(&self) -> AABB<T, D> {
+        (**self).bound()
+    }
+}
+
+impl<T: Float, D: Dim, B: Bounded<T, D> + ?Sized> Bounded<T, D> for Box<B> {
+    fn bound(&self) -> AABB<T, D> {
+        (**self).bound()
+    }
+}
+
+impl<T: Float, D: Dim, B: Bounded<T, D> + ?Sized> Bounded<T, D> for Arc<B> {
+    fn bound(&self) -> AABB<T, D> {
+        (**self).bound()
+    }
 }
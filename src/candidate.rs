@@ -1,76 +1,214 @@
-use crate::AABB;
-use crate::plane::{Dimension, Plane};
 use std::cmp::Ordering;
 
-pub type Candidates = Vec<Candidate>;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
-#[derive(Debug, Clone)]
-pub struct Candidate {
-    pub plane: Plane,
-    pub is_left: bool,
-    pub shape: usize,
+use crate::dim::Dim;
+use crate::plane::Dimension;
+use crate::{AABB, Float};
+
+/// Type of a candidate event.
+///
+/// At the same position, end events come first, then planar events and then start events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EventKind {
+    /// The shape ends at the position.
+    End,
+    /// The shape is flat and lies at the position.
+    Planar,
+    /// The shape starts at the position.
+    Start,
 }
 
-impl Candidate {
-    fn new(plane: Plane, is_left: bool, index: usize) -> Self {
-        Candidate {
-            plane,
-            is_left,
-            shape: index,
-        }
-    }
+/// Split candidate (event) of a shape on an axis.
+#[derive(Debug, Clone, Copy)]
+pub struct Candidate<T: Float> {
+    pub pos: T,
+    pub kind: EventKind,
+    /// Index of the shape among the shapes of the node.
+    pub shape: u32,
+}
 
-    /// Return candidates (splits candidates) for all dimension.
-    pub fn gen_candidates(shape: usize, bb: &AABB) -> Candidates {
-        vec![
-            Candidate::new(Plane::new_x(bb.min.x), true, shape),
-            Candidate::new(Plane::new_x(bb.max.x), false, shape),
-            Candidate::new(Plane::new_y(bb.min.y), true, shape),
-            Candidate::new(Plane::new_y(bb.max.y), false, shape),
-            Candidate::new(Plane::new_z(bb.min.z), true, shape),
-            Candidate::new(Plane::new_z(bb.max.z), false, shape),
-        ]
+impl<T: Float> Candidate<T> {
+    fn new(pos: T, kind: EventKind, shape: u32) -> Self {
+        Candidate { pos, kind, shape }
     }
 
-    pub fn dimension(&self) -> Dimension {
-        self.plane.dimension
+    /// Check if the candidate is the start or the planar event of its shape, i.e. the
+    /// first event of the shape on its axis.
+    pub fn is_start(&self) -> bool {
+        self.kind != EventKind::End
     }
+}
 
-    pub fn position(&self) -> f32 {
-        self.plane.pos
+impl<T: Float> Ord for Candidate<T> {
+    /// Candidates are ordered by position, kind and shape.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.pos
+            .total_cmp(&other.pos)
+            .then(self.kind.cmp(&other.kind))
+            .then(self.shape.cmp(&other.shape))
     }
+}
 
-    pub fn is_left(&self) -> bool {
-        self.is_left
+impl<T: Float> PartialOrd for Candidate<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
+}
 
-    pub fn is_right(&self) -> bool {
-        !self.is_left
+impl<T: Float> PartialEq for Candidate<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
     }
 }
 
-impl Ord for Candidate {
-    fn cmp(&self, other: &Self) -> Ordering {
-        if self.position() < other.position() {
-            Ordering::Less
-        } else {
-            Ordering::Greater
+impl<T: Float> Eq for Candidate<T> {}
+
+/// Split candidates of the shapes of a node, sorted on each axis.
+///
+/// Shapes are identified by their index among the shapes of the node. This index is
+/// increasing with the index of the shape in the whole tree, so the candidates of the
+/// children stay sorted when splitting them.
+#[derive(Debug, Clone)]
+pub struct Candidates<T: Float> {
+    axes: Vec<Vec<Candidate<T>>>,
+}
+
+impl<T: Float> Candidates<T> {
+    /// Return the sorted candidates of shapes given their bounding boxes.
+    /// A shape flat on an axis has a single planar candidate on this axis.
+    #[cfg_attr(not(feature = "rayon"), allow(unused_variables))]
+    pub fn new<D: Dim>(bounds: &[AABB<T, D>], parallel: bool) -> Self {
+        assert!(bounds.len() <= u32::MAX as usize, "too many shapes");
+
+        #[cfg(feature = "rayon")]
+        if parallel {
+            let axes = Dimension::all::<D>()
+                .collect::<Vec<_>>()
+                .into_par_iter()
+                .map(|dim| {
+                    let mut axis: Vec<_> = bounds
+                        .par_iter()
+                        .enumerate()
+                        .flat_map_iter(|(shape, bb)| gen_candidates(shape, bb, dim))
+                        .collect();
+                    axis.par_sort_unstable();
+                    axis
+                })
+                .collect();
+            return Candidates { axes };
         }
+
+        let axes = Dimension::all::<D>()
+            .map(|dim| {
+                let mut axis = Vec::with_capacity(bounds.len() * 2);
+                for (shape, bb) in bounds.iter().enumerate() {
+                    axis.extend(gen_candidates(shape, bb, dim));
+                }
+                axis.sort_unstable();
+                axis
+            })
+            .collect();
+        Candidates { axes }
     }
-}
-impl PartialOrd for Candidate {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+
+    /// Create the candidates of a node from the sorted candidates of each axis.
+    pub fn from_axes(axes: Vec<Vec<Candidate<T>>>) -> Self {
+        Candidates { axes }
     }
-}
 
-impl PartialEq for Candidate {
-    fn eq(&self, other: &Self) -> bool {
-        self.position() == other.position() && self.dimension() == other.dimension()
+    /// Retrieve the sorted candidates on an axis.
+    pub fn axis(&self, dim: Dimension) -> &[Candidate<T>] {
+        &self.axes[dim.0]
     }
+
+    /// Iterate over the axes and their sorted candidates.
+    pub fn iter(&self) -> impl Iterator<Item = (Dimension, &[Candidate<T>])> {
+        self.axes
+            .iter()
+            .enumerate()
+            .map(|(dim, axis)| (Dimension(dim), axis.as_slice()))
+    }
+
+    /// Split the candidates between the two children of a node given the side of each
+    /// shape. The relative order of the candidates is kept.
+    #[cfg_attr(not(feature = "rayon"), allow(unused_variables))]
+    pub fn split(&self, sides: &[Side], parallel: bool) -> (Self, Self) {
+        // Index of the shapes in the children
+        let mut left_shapes = vec![u32::MAX; sides.len()];
+        let mut right_shapes = vec![u32::MAX; sides.len()];
+        let (mut nb_left, mut nb_right) = (0, 0);
+        for (shape, side) in sides.iter().enumerate() {
+            if !matches!(side, Side::Right) {
+                left_shapes[shape] = nb_left;
+                nb_left += 1;
+            }
+            if !matches!(side, Side::Left) {
+                right_shapes[shape] = nb_right;
+                nb_right += 1;
+            }
+        }
+
+        let split_axis = |axis: &Vec<Candidate<T>>| {
+            let mut left = Vec::with_capacity(nb_left as usize * 2);
+            let mut right = Vec::with_capacity(nb_right as usize * 2);
+            for &candidate in axis {
+                let shape = candidate.shape as usize;
+                if left_shapes[shape] != u32::MAX {
+                    left.push(Candidate {
+                        shape: left_shapes[shape],
+                        ..candidate
+                    });
+                }
+                if right_shapes[shape] != u32::MAX {
+                    right.push(Candidate {
+                        shape: right_shapes[shape],
+                        ..candidate
+                    });
+                }
+            }
+            (left, right)
+        };
+
+        #[cfg(feature = "rayon")]
+        let (left, right): (Vec<_>, Vec<_>) = if parallel {
+            self.axes.par_iter().map(split_axis).unzip()
+        } else {
+            self.axes.iter().map(split_axis).unzip()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let (left, right): (Vec<_>, Vec<_>) = self.axes.iter().map(split_axis).unzip();
+
+        (Candidates { axes: left }, Candidates { axes: right })
+    }
+}
+
+/// Return the candidates of a shape on an axis.
+fn gen_candidates<T: Float, D: Dim>(
+    shape: usize,
+    bb: &AABB<T, D>,
+    dim: Dimension,
+) -> impl Iterator<Item = Candidate<T>> {
+    let shape = shape as u32;
+    events(bb, dim).map(move |(pos, kind)| Candidate::new(pos, kind, shape))
 }
 
-impl Eq for Candidate {}
+/// Return the positions and kinds of the candidates of a bounding box on an axis.
+pub fn events<T: Float, D: Dim>(
+    bb: &AABB<T, D>,
+    dim: Dimension,
+) -> impl Iterator<Item = (T, EventKind)> + use<T, D> {
+    // Adding zero turns `-0.` into `0.` so both are the same position in the total order
+    let min = bb.min[dim.0] + T::zero();
+    let max = bb.max[dim.0] + T::zero();
+    let events = if min == max {
+        [Some((min, EventKind::Planar)), None]
+    } else {
+        [Some((min, EventKind::Start)), Some((max, EventKind::End))]
+    };
+    events.into_iter().flatten()
+}
 
 /// Useful to classify candidates
 #[derive(Debug, Clone, Copy)]
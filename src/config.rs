@@ -1,21 +1,79 @@
+use std::sync::Arc;
+
+use crate::Float;
+use crate::progress::{CancellationToken, ProgressCallback};
+
 static DEFAULT_COST_TRAVERSAL: f32 = 15.;
 static DEFAULT_COST_INTERSECTION: f32 = 20.;
 static DEFAULT_EMPTY_CUT_BONUS: f32 = 0.2;
+static DEFAULT_PARALLEL_THRESHOLD: usize = 1024;
+
+/// Threads used to build a KD-tree.
+#[derive(Clone, Debug, Default)]
+pub enum Threading {
+    /// Build in the current rayon thread pool (the global one unless called from inside
+    /// another pool).
+    ///
+    /// **Note**: Without the `rayon` feature the build is sequential.
+    #[default]
+    Global,
+    /// Build in the given rayon thread pool.
+    #[cfg(feature = "rayon")]
+    Pool(Arc<rayon::ThreadPool>),
+    /// Build on the calling thread only.
+    Sequential,
+}
+
+impl Threading {
+    /// Run `op` in the thread pool.
+    pub(crate) fn install<R, OP>(&self, op: OP) -> R
+    where
+        R: Send,
+        OP: FnOnce() -> R + Send,
+    {
+        match self {
+            #[cfg(feature = "rayon")]
+            Threading::Pool(pool) => pool.install(op),
+            _ => op(),
+        }
+    }
+}
 
 /// Configuration for the builder.
-#[derive(Clone, Copy, Debug)]
-pub struct BuilderConfig {
+///
+/// The configuration is generic over the floating type of the tree. When nothing else
+/// gives that type, as in a `let` binding, annotate it: `BuilderConfig::default()` alone
+/// doesn't compile since default type parameters aren't used for inference.
+///
+/// ```
+/// use kdtree_ray::BuilderConfig;
+///
+/// let config: BuilderConfig = BuilderConfig::default();
+/// let config_f64 = BuilderConfig::<f64>::default();
+/// assert_eq!(config.cost_traversal(), 15.);
+/// assert_eq!(config_f64.cost_traversal(), 15.);
+/// ```
+#[derive(Clone, Debug)]
+pub struct BuilderConfig<T: Float = f32> {
     /// Cost of a traversal in the kdtree.
-    cost_traversal: f32,
+    cost_traversal: T,
     /// Cost of an intersection test.
-    cost_intersection: f32,
+    cost_intersection: T,
     /// Bonus (between `0.` and `1.`) for cutting an empty space:
     /// * `1.` means that cutting an empty space is in any case better than cutting a full space.
     /// * `0.` means that cutting an empty space isn't better than cutting a full space.
-    empty_cut_bonus: f32,
+    empty_cut_bonus: T,
+    /// Threads used to build the tree.
+    threading: Threading,
+    /// Number of shapes under which a node is built sequentially.
+    parallel_threshold: usize,
+    /// Callback fed with the progress of the build.
+    pub(crate) progress: Option<ProgressCallback>,
+    /// Token used to cancel the build.
+    cancellation: Option<CancellationToken>,
 }
 
-impl BuilderConfig {
+impl<T: Float> BuilderConfig<T> {
     /// Create a new `BuilderConfig` given the cost of a traversal, the cost of an intersection
     /// test and the bonus for cutting an empty space.
     ///
@@ -24,43 +82,139 @@ impl BuilderConfig {
     /// * If `cost_traversal` is not strictly positive.
     /// * If `cost_intersection` is not strictly positive.
     /// * If `empty_cut_bonus` is not between `0.` and `1.`.
-    pub fn new(cost_traversal: f32, cost_intersection: f32, empty_cut_bonus: f32) -> Self {
-        assert!(cost_traversal > 0.);
-        assert!(cost_intersection > 0.);
-        assert!((0. ..=1.).contains(&empty_cut_bonus));
+    pub fn new(cost_traversal: T, cost_intersection: T, empty_cut_bonus: T) -> Self {
+        assert!(cost_traversal > T::zero());
+        assert!(cost_intersection > T::zero());
+        assert!((T::zero()..=T::one()).contains(&empty_cut_bonus));
         BuilderConfig {
             cost_traversal,
             cost_intersection,
             empty_cut_bonus,
+            threading: Threading::default(),
+            parallel_threshold: DEFAULT_PARALLEL_THRESHOLD,
+            progress: None,
+            cancellation: None,
         }
     }
 
+    /// Set the threads used to build the tree.
+    pub fn with_threading(mut self, threading: Threading) -> Self {
+        self.threading = threading;
+        self
+    }
+
+    /// Set the number of shapes under which a node is built sequentially.
+    /// Spawning parallel tasks for small nodes costs more than it saves.
+    pub fn with_parallel_threshold(mut self, parallel_threshold: usize) -> Self {
+        self.parallel_threshold = parallel_threshold;
+        self
+    }
+
+    /// Set a callback fed with the progress of the build, between `0.` and `1.`: the
+    /// fraction of the shapes placed in their final leaves. It's called each time the
+    /// progress reaches a new percent, from the threads building the tree.
+    pub fn with_progress<F: Fn(f32) + Send + Sync + 'static>(mut self, progress: F) -> Self {
+        self.progress = Some(ProgressCallback(Arc::new(progress)));
+        self
+    }
+
+    /// Set a token to cancel the build, see
+    /// [KDTree::try_build_config](struct.KDTree.html#method.try_build_config).
+    ///
+    /// **Note**: Edits of the built tree (see [KDTree::insert](struct.KDTree.html#method.insert))
+    /// can't be cancelled and don't report their progress.
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
     /// Retrieve the cost of a traversal.
-    pub fn cost_traversal(&self) -> f32 {
+    pub fn cost_traversal(&self) -> T {
         self.cost_traversal
     }
 
     /// Retrieve the cost of an intersection.
-    pub fn cost_intersection(&self) -> f32 {
+    pub fn cost_intersection(&self) -> T {
         self.cost_intersection
     }
 
     /// Retrieve the bonus for cutting an empty space.
-    pub fn empty_cut_bonus(&self) -> f32 {
+    pub fn empty_cut_bonus(&self) -> T {
         self.empty_cut_bonus
     }
+
+    /// Retrieve the threads used to build the tree.
+    pub fn threading(&self) -> &Threading {
+        &self.threading
+    }
+
+    /// Retrieve the number of shapes under which a node is built sequentially.
+    pub fn parallel_threshold(&self) -> usize {
+        self.parallel_threshold
+    }
+
+    /// Retrieve the token used to cancel the build.
+    pub fn cancellation(&self) -> Option<&CancellationToken> {
+        self.cancellation.as_ref()
+    }
+
+    /// Copy of the configuration kept by a built tree for its edits: only the costs and the
+    /// parallel threshold. The threads, the progress callback and the cancellation token of
+    /// the build aren't kept alive by the tree.
+    pub(crate) fn for_edits(&self) -> Self {
+        BuilderConfig {
+            cost_traversal: self.cost_traversal,
+            cost_intersection: self.cost_intersection,
+            empty_cut_bonus: self.empty_cut_bonus,
+            threading: Threading::default(),
+            parallel_threshold: self.parallel_threshold,
+            progress: None,
+            cancellation: None,
+        }
+    }
+
+    /// Check if a node of `nb_shapes` shapes should be built in parallel.
+    pub(crate) fn is_parallel(&self, nb_shapes: usize) -> bool {
+        cfg!(feature = "rayon")
+            && !matches!(self.threading, Threading::Sequential)
+            && nb_shapes >= self.parallel_threshold
+    }
+
+    /// Run two closures, in parallel if the node of `nb_shapes` shapes is big enough.
+    pub(crate) fn join<A, B, RA, RB>(&self, nb_shapes: usize, a: A, b: B) -> (RA, RB)
+    where
+        A: FnOnce() -> RA + Send,
+        B: FnOnce() -> RB + Send,
+        RA: Send,
+        RB: Send,
+    {
+        #[cfg(feature = "rayon")]
+        if self.is_parallel(nb_shapes) {
+            return rayon::join(a, b);
+        }
+        #[cfg(not(feature = "rayon"))]
+        let _ = nb_shapes;
+        (a(), b())
+    }
 }
 
-impl Default for BuilderConfig {
+impl<T: Float> Default for BuilderConfig<T> {
     /// Create a new `BuilderConfig` with the default values.
     /// * Traversal cost: `15.`
     /// * Intersection cost: `20.`
     /// * Empty cut bonus: `0.2`
+    /// * Threading: [Global](enum.Threading.html#variant.Global)
+    /// * Parallel threshold: `1024` shapes
+    /// * No progress callback nor cancellation token
     fn default() -> Self {
         BuilderConfig {
-            cost_traversal: DEFAULT_COST_TRAVERSAL,
-            cost_intersection: DEFAULT_COST_INTERSECTION,
-            empty_cut_bonus: DEFAULT_EMPTY_CUT_BONUS,
+            cost_traversal: T::cast(DEFAULT_COST_TRAVERSAL),
+            cost_intersection: T::cast(DEFAULT_COST_INTERSECTION),
+            empty_cut_bonus: T::cast(DEFAULT_EMPTY_CUT_BONUS),
+            threading: Threading::default(),
+            parallel_threshold: DEFAULT_PARALLEL_THRESHOLD,
+            progress: None,
+            cancellation: None,
         }
     }
 }
@@ -0,0 +1,229 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::time::Instant;
+
+use crate::Float;
+use crate::aabb::*;
+use crate::candidate::{Candidates, Side};
+use crate::config::BuilderConfig;
+use crate::dim::Dim;
+use crate::edit::relayout;
+use crate::kdnode::{KDTreeNode, find_split, leaf_cost, leaf_shapes, split_node, split_space};
+use crate::kdtree::KDTree;
+use crate::plane::Plane;
+use crate::progress::{BuildProgress, split_share};
+
+/// Report of a [KDTree::build_with_deadline](struct.KDTree.html#method.build_with_deadline).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DeadlineReport {
+    /// Fraction of the work finished before the deadline, between `0.` and `1.`: the
+    /// fraction of the shapes placed in the leaves they would have in a full build.
+    pub completed: f32,
+    /// Number of nodes that weren't split because the deadline was reached.
+    pub nb_unfinished_nodes: usize,
+}
+
+impl DeadlineReport {
+    /// Check if the build finished before the deadline, the tree is then the same as the
+    /// one of a full build.
+    pub fn is_complete(&self) -> bool {
+        self.nb_unfinished_nodes == 0
+    }
+}
+
+impl<T: Float, D: Dim> KDTree<T, D> {
+    /// Build a KD-tree, stopping at `deadline`.
+    ///
+    /// Nodes are split by decreasing gain of the Surface Area Heuristic, so the splits
+    /// improving queries the most come first. When the deadline is reached, the nodes not
+    /// split yet become leaves. The tree is always valid, just slower to query than a full
+    /// build. A cancelled [token](struct.BuilderConfig.html#method.with_cancellation) stops
+    /// the build like the deadline.
+    ///
+    /// Nodes are split one at a time, only the work inside a node uses the threads of
+    /// `config`.
+    ///
+    /// Panic if the `shapes` is empty.
+    pub fn build_with_deadline<S: Bounded<T, D>>(
+        shapes: &[S],
+        config: &BuilderConfig<T>,
+        deadline: Instant,
+    ) -> (Self, DeadlineReport) {
+        assert!(!shapes.is_empty());
+        let bounds: Vec<AABB<T, D>> = shapes.iter().map(|s| s.bound()).collect();
+        let space = AABB::enclosing(&bounds);
+
+        let nb_shapes = shapes.len();
+        let progress = BuildProgress::new(config);
+        let (depth, tree, nb_unfinished_nodes) = config.threading().install(|| {
+            let parallel = config.is_parallel(nb_shapes);
+            let candidates = Candidates::new(&bounds, parallel);
+            let root = Task {
+                node: 0,
+                space: space.clone(),
+                candidates,
+                shapes: (0..nb_shapes).collect(),
+                share: 1.,
+            };
+            build_until(config, &progress, root, deadline)
+        });
+        if nb_unfinished_nodes == 0 {
+            progress.finish();
+        }
+
+        let mut kdtree = KDTree {
+            space,
+            tree,
+            depth,
+            config: config.for_edits(),
+            bounds,
+            reference_cost: T::zero(),
+            stale_references: false,
+        };
+        kdtree.reference_cost = kdtree.sah_cost();
+        let report = DeadlineReport {
+            completed: progress.done(),
+            nb_unfinished_nodes,
+        };
+        (kdtree, report)
+    }
+}
+
+/// Node waiting to be built.
+struct Task<T: Float, D: Dim> {
+    /// Index of the node in the tree.
+    node: usize,
+    space: AABB<T, D>,
+    candidates: Candidates<T>,
+    shapes: Vec<usize>,
+    share: f64,
+}
+
+/// Node waiting to be split, ordered by the gain of its split.
+struct PendingSplit<T: Float, D: Dim> {
+    gain: T,
+    /// Order of creation, older nodes come first on equal gains.
+    order: usize,
+    split: Plane<T>,
+    planar_side: Side,
+    task: Task<T, D>,
+}
+
+impl<T: Float, D: Dim> Ord for PendingSplit<T, D> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.gain
+            .total_cmp(&other.gain)
+            .then(other.order.cmp(&self.order))
+    }
+}
+
+impl<T: Float, D: Dim> PartialOrd for PendingSplit<T, D> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Float, D: Dim> PartialEq for PendingSplit<T, D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: Float, D: Dim> Eq for PendingSplit<T, D> {}
+
+/// Build the tree of the root task until the deadline.
+/// Return the depth of the tree, its nodes and the number of nodes left unfinished.
+fn build_until<T: Float, D: Dim>(
+    config: &BuilderConfig<T>,
+    progress: &BuildProgress,
+    root: Task<T, D>,
+    deadline: Instant,
+) -> (usize, Vec<KDTreeNode<T, D>>, usize) {
+    let mut nodes = vec![KDTreeNode::Leaf { shapes: vec![] }];
+    let mut pending = BinaryHeap::new();
+    let mut order = 0;
+
+    // Leaves are finished right away, other nodes wait for their split
+    let mut schedule = |nodes: &mut Vec<KDTreeNode<T, D>>,
+                        pending: &mut BinaryHeap<PendingSplit<T, D>>,
+                        task: Task<T, D>| {
+        let nb_shapes = task.shapes.len();
+        match find_split(config, &task.space, &task.candidates, nb_shapes) {
+            Some((cost, split, planar_side)) => {
+                // Gain weighted by the probability to hit the node
+                let gain = (leaf_cost(config, nb_shapes) - cost) * task.space.surface();
+                pending.push(PendingSplit {
+                    gain,
+                    order,
+                    split,
+                    planar_side,
+                    task,
+                });
+                order += 1;
+            }
+            None => {
+                progress.finish_leaf(task.share);
+                let shapes = leaf_shapes(&task.candidates, &task.shapes);
+                nodes[task.node] = KDTreeNode::Leaf { shapes };
+            }
+        }
+    };
+    schedule(&mut nodes, &mut pending, root);
+
+    while Instant::now() < deadline && progress.check().is_ok() {
+        let Some(PendingSplit {
+            split,
+            planar_side,
+            task,
+            ..
+        }) = pending.pop()
+        else {
+            break;
+        };
+        let (l_space, r_space) = split_space(&task.space, &split);
+        let ((left_candidates, left_shapes), (right_candidates, right_shapes)) =
+            split_node(config, &task.candidates, &task.shapes, &split, planar_side);
+        // The children are scheduled without the candidates of their parent
+        drop((task.candidates, task.shapes));
+        let (l_share, r_share) = split_share(task.share, left_shapes.len(), right_shapes.len());
+
+        let (l_child, r_child) = (nodes.len(), nodes.len() + 1);
+        nodes.push(KDTreeNode::Leaf { shapes: vec![] });
+        nodes.push(KDTreeNode::Leaf { shapes: vec![] });
+        nodes[task.node] = KDTreeNode::Node {
+            l_child,
+            l_space: l_space.clone(),
+            r_child,
+            r_space: r_space.clone(),
+            split,
+        };
+        let left = Task {
+            node: l_child,
+            space: l_space,
+            candidates: left_candidates,
+            shapes: left_shapes,
+            share: l_share,
+        };
+        schedule(&mut nodes, &mut pending, left);
+        let right = Task {
+            node: r_child,
+            space: r_space,
+            candidates: right_candidates,
+            shapes: right_shapes,
+            share: r_share,
+        };
+        schedule(&mut nodes, &mut pending, right);
+    }
+
+    // Nodes not split in time become leaves
+    let nb_unfinished_nodes = pending.len();
+    for PendingSplit { task, .. } in pending {
+        let shapes = leaf_shapes(&task.candidates, &task.shapes);
+        nodes[task.node] = KDTreeNode::Leaf { shapes };
+    }
+
+    // Put the nodes in the order of a full build
+    let mut tree = Vec::with_capacity(nodes.len());
+    let depth = relayout(&mut nodes, 0, &mut tree);
+    (depth, tree, nb_unfinished_nodes)
+}
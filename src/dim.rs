@@ -0,0 +1,63 @@
+use std::fmt::Debug;
+use std::ops::{Index, IndexMut};
+
+use crate::Float;
+
+/// Dimensionality of the space of a KD-tree.
+///
+/// It's implemented by [D2](struct.D2.html) (2D rectangles and rays) and
+/// [D3](struct.D3.html) (3D boxes and rays, the default).
+pub trait Dim: Clone + Copy + Debug + Default + PartialEq + Eq + Send + Sync + 'static {
+    /// Number of axes of the space.
+    const N: usize;
+
+    /// Vector type used for positions and directions.
+    type Vector<T: Float>: Copy
+        + Debug
+        + PartialEq
+        + Send
+        + Sync
+        + Index<usize, Output = T>
+        + IndexMut<usize>;
+
+    /// Create a vector with all its coordinates set to `value`.
+    fn splat<T: Float>(value: T) -> Self::Vector<T>;
+
+    /// Surface area of a box (its perimeter in 2D) given its extent along each axis.
+    /// This is the measure used by the Surface Area Heuristic.
+    fn surface<T: Float>(extent: &Self::Vector<T>) -> T;
+}
+
+/// 2D space, positions are `cgmath::Vector2`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct D2;
+
+impl Dim for D2 {
+    const N: usize = 2;
+    type Vector<T: Float> = cgmath::Vector2<T>;
+
+    fn splat<T: Float>(value: T) -> Self::Vector<T> {
+        cgmath::Vector2::new(value, value)
+    }
+
+    fn surface<T: Float>(extent: &Self::Vector<T>) -> T {
+        T::cast(2.) * (extent.x + extent.y)
+    }
+}
+
+/// 3D space, positions are `cgmath::Vector3`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct D3;
+
+impl Dim for D3 {
+    const N: usize = 3;
+    type Vector<T: Float> = cgmath::Vector3<T>;
+
+    fn splat<T: Float>(value: T) -> Self::Vector<T> {
+        cgmath::Vector3::new(value, value, value)
+    }
+
+    fn surface<T: Float>(extent: &Self::Vector<T>) -> T {
+        T::cast(2.) * (extent.x * extent.y + extent.x * extent.z + extent.y * extent.z)
+    }
+}
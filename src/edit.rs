@@ -0,0 +1,272 @@
+use std::mem;
+
+use crate::Float;
+use crate::aabb::*;
+use crate::candidate::*;
+use crate::dim::Dim;
+use crate::kdnode::{KDTreeNode, build_tree, shape_side, split_space};
+use crate::kdtree::KDTree;
+use crate::progress::BuildProgress;
+use crate::refit::DEGRADATION_THRESHOLD;
+
+/// Leaves with more shapes than this threshold are split again after an insertion.
+static LEAF_SPLIT_THRESHOLD: usize = 8;
+
+/// Check if a leaf should be split after an insertion brought it to `nb_shapes` shapes.
+/// The Surface Area Heuristic can keep a big leaf (shapes on top of each other for example),
+/// so leaves are only split again each time their size doubles instead of after every
+/// insertion.
+fn should_split(nb_shapes: usize) -> bool {
+    nb_shapes > LEAF_SPLIT_THRESHOLD
+        && (nb_shapes == LEAF_SPLIT_THRESHOLD + 1 || nb_shapes.is_power_of_two())
+}
+
+impl<T: Float, D: Dim> KDTree<T, D> {
+    /// Insert a shape in the tree given its index and its bounding box.
+    ///
+    /// The shape is added to every leaf it overlaps, leaves that become too big are split
+    /// using the Surface Area Heuristic. Leaves that stay too big after a split are split
+    /// again once their size doubled. Splits run in the current thread pool, the tree doesn't
+    /// keep the threads of the configuration it was built with.
+    ///
+    /// ### Panics
+    ///
+    /// * If a shape with the same index is already in the tree.
+    /// * If `bb` is empty.
+    pub fn insert(&mut self, index: usize, bb: &AABB<T, D>) {
+        assert!(
+            !self.contains_shape(index),
+            "shape {index} is already in the tree"
+        );
+        assert!(!bb.is_empty());
+        if self.bounds.len() <= index {
+            self.bounds.resize(index + 1, AABB::empty());
+        }
+        self.bounds[index] = bb.clone();
+        self.space.merge(bb);
+
+        // Push the shape down to the leaves it overlaps.
+        // Track the region delimited by splitting planes and the space stored for each node.
+        let mut full_leaves = vec![];
+        let mut stack = vec![(0, self.space.clone(), self.space.clone(), 1)];
+        while let Some((node, region, space, depth)) = stack.pop() {
+            match &mut self.tree[node] {
+                KDTreeNode::Leaf { shapes } => {
+                    shapes.push(index);
+                    if should_split(shapes.len()) {
+                        full_leaves.push((node, region, space, depth));
+                    }
+                }
+                KDTreeNode::Node {
+                    l_child,
+                    l_space,
+                    r_child,
+                    r_space,
+                    split,
+                } => {
+                    let (l_region, r_region) = split_space(&region, split);
+                    let (min, max) = split.axis_bounds(bb);
+                    // Shapes lying on the splitting plane go to the left
+                    if max > split.pos {
+                        r_space.merge(&bb.intersection(&r_region));
+                        stack.push((*r_child, r_region, r_space.clone(), depth + 1));
+                    }
+                    if min < split.pos || (min == split.pos && max == split.pos) {
+                        l_space.merge(&bb.intersection(&l_region));
+                        stack.push((*l_child, l_region, l_space.clone(), depth + 1));
+                    }
+                }
+            }
+        }
+
+        for (node, region, space, depth) in full_leaves {
+            self.split_leaf(node, &region, &space, depth);
+        }
+    }
+
+    /// Remove a shape from the tree given its index.
+    /// Subtrees left without any shape are collapsed into empty leaves.
+    ///
+    /// Only the leaves the shape lies in are visited, unless a [refit](#method.refit) left
+    /// references in leaves shapes don't lie in anymore: every leaf is visited then.
+    ///
+    /// Return `false` if the shape wasn't in the tree.
+    pub fn remove(&mut self, index: usize) -> bool {
+        if !self.contains_shape(index) {
+            return false;
+        }
+        // Removed shapes have an empty bounding box
+        let bb = mem::take(&mut self.bounds[index]);
+
+        // Remove the shape from its leaves, keeping the inner nodes visited parents first
+        let mut nodes = vec![];
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            match &mut self.tree[node] {
+                KDTreeNode::Leaf { shapes } => shapes.retain(|&shape| shape != index),
+                KDTreeNode::Node {
+                    l_child,
+                    r_child,
+                    split,
+                    ..
+                } => {
+                    nodes.push(node);
+                    let side = if self.stale_references {
+                        Some(Side::Both)
+                    } else {
+                        shape_side(&bb, split)
+                    };
+                    if !matches!(side, Some(Side::Left)) {
+                        stack.push(*r_child);
+                    }
+                    if !matches!(side, Some(Side::Right)) {
+                        stack.push(*l_child);
+                    }
+                }
+            }
+        }
+
+        // Collapse the subtrees left without any shape, children first
+        let mut collapsed = false;
+        for &node in nodes.iter().rev() {
+            if let KDTreeNode::Node {
+                l_child, r_child, ..
+            } = &self.tree[node]
+                && self.is_empty_leaf(*l_child)
+                && self.is_empty_leaf(*r_child)
+            {
+                self.tree[node] = KDTreeNode::Leaf { shapes: vec![] };
+                collapsed = true;
+            }
+        }
+        if collapsed {
+            self.compact();
+        }
+        true
+    }
+
+    /// Estimate how much the tree degraded since its last full build.
+    ///
+    /// This is the ratio between the current SAH cost of the tree and its cost right after
+    /// the build. `1.` means no degradation, higher values mean queries got slower, either
+    /// because the tree grew or because edits made it less efficient. In both cases a full
+    /// rebuild is worth it once the ratio is high.
+    pub fn degradation(&self) -> T {
+        if self.reference_cost <= T::zero() {
+            return T::one();
+        }
+        self.sah_cost() / self.reference_cost
+    }
+
+    /// Check if the tree degraded enough after insertions and removals to be worth a full
+    /// rebuild (see [degradation](#method.degradation)).
+    pub fn rebuild_recommended(&self) -> bool {
+        self.degradation() > T::cast(DEGRADATION_THRESHOLD)
+    }
+
+    /// Check if a shape is part of the tree.
+    pub(crate) fn contains_shape(&self, index: usize) -> bool {
+        index < self.bounds.len() && !self.bounds[index].is_empty()
+    }
+
+    /// Replace a leaf by a subtree built with the Surface Area Heuristic.
+    fn split_leaf(&mut self, node: usize, region: &AABB<T, D>, space: &AABB<T, D>, depth: usize) {
+        let KDTreeNode::Leaf { shapes } = &self.tree[node] else {
+            return;
+        };
+        // Shapes are sorted to build the same subtree as a full build would
+        let mut shapes = shapes.clone();
+        shapes.sort_unstable();
+        let bounds: Vec<AABB<T, D>> = shapes.iter().map(|&s| self.bounds[s].clone()).collect();
+        let candidates = Candidates::new(&bounds, false);
+        let config = &self.config;
+        // Edits can't be cancelled and don't report their progress
+        let progress = BuildProgress::none();
+        let (sub_depth, subtree) =
+            build_tree(config, &progress, region, candidates, shapes, 1.).unwrap();
+        if subtree.len() == 1 {
+            // The leaf is kept as it is, see `should_split`
+            return;
+        }
+
+        // The root of the subtree replaces the leaf and other nodes are appended to the tree.
+        // Spaces are clipped to stay inside the space of the leaf.
+        let offset = self.tree.len() - 1;
+        let mut subtree = subtree.into_iter().map(|mut node| {
+            if let KDTreeNode::Node {
+                l_child,
+                l_space,
+                r_child,
+                r_space,
+                ..
+            } = &mut node
+            {
+                *l_child += offset;
+                *r_child += offset;
+                *l_space = l_space.intersection(space);
+                *r_space = r_space.intersection(space);
+            }
+            node
+        });
+        self.tree[node] = subtree.next().unwrap();
+        self.tree.extend(subtree);
+        self.depth = self.depth.max(depth + sub_depth - 1);
+    }
+
+    fn is_empty_leaf(&self, node: usize) -> bool {
+        matches!(&self.tree[node], KDTreeNode::Leaf { shapes } if shapes.is_empty())
+    }
+
+    /// Drop nodes that aren't reachable from the root anymore.
+    fn compact(&mut self) {
+        let mut old_tree = mem::take(&mut self.tree);
+        let mut tree = Vec::with_capacity(old_tree.len());
+        self.depth = relayout(&mut old_tree, 0, &mut tree);
+        self.tree = tree;
+    }
+}
+
+/// Move the subtree rooted at `node` from `old_tree` to the end of `tree`.
+/// Return the depth of the subtree.
+///
+/// The nodes are visited with an explicit stack so that deep trees (read from a file for
+/// example) don't overflow the call stack.
+pub(crate) fn relayout<T: Float, D: Dim>(
+    old_tree: &mut [KDTreeNode<T, D>],
+    node: usize,
+    tree: &mut Vec<KDTreeNode<T, D>>,
+) -> usize {
+    let mut depth = 0;
+    // Nodes to move with the index of their parent in `tree` (if any), whether they are its
+    // left child and their depth
+    let mut stack = vec![(node, None, true, 1)];
+    while let Some((node, parent, is_left, node_depth)) = stack.pop() {
+        let index = tree.len();
+        tree.push(mem::replace(
+            &mut old_tree[node],
+            KDTreeNode::Leaf { shapes: vec![] },
+        ));
+        if let Some(parent) = parent
+            && let KDTreeNode::Node {
+                l_child, r_child, ..
+            } = &mut tree[parent]
+        {
+            if is_left {
+                *l_child = index;
+            } else {
+                *r_child = index;
+            }
+        }
+        match &tree[index] {
+            KDTreeNode::Leaf { .. } => depth = depth.max(node_depth),
+            KDTreeNode::Node {
+                l_child, r_child, ..
+            } => {
+                // The left child is popped first to keep the order of a built tree
+                stack.push((*r_child, Some(index), false, node_depth + 1));
+                stack.push((*l_child, Some(index), true, node_depth + 1));
+            }
+        }
+    }
+    depth
+}
@@ -0,0 +1,121 @@
+use std::io::{self, Write};
+
+use crate::Float;
+use crate::aabb::*;
+use crate::dim::Dim;
+use crate::kdnode::KDTreeNode;
+use crate::kdtree::KDTree;
+
+/// Edges of a box given as pairs of corner indices (see `write_box`).
+static BOX_EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (1, 3),
+    (3, 2),
+    (2, 0),
+    (4, 5),
+    (5, 7),
+    (7, 6),
+    (6, 4),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+impl<T: Float> KDTree<T> {
+    /// Export the spaces of the nodes as a wireframe [OBJ](https://en.wikipedia.org/wiki/Wavefront_.obj_file)
+    /// file. Each box is made of line segments and boxes are grouped by depth
+    /// (`depth_0` for the root, `depth_1` for its children, ...).
+    ///
+    /// Only nodes with a depth lower or equal to `max_depth` are exported.
+    pub fn export_obj<W: Write>(&self, writer: &mut W, max_depth: usize) -> io::Result<()> {
+        // Collect the spaces of the nodes for each depth
+        let mut spaces: Vec<Vec<&AABB<T>>> = vec![];
+        let mut stack = vec![(0, 0, &self.space)];
+        while let Some((index, depth, space)) = stack.pop() {
+            if depth > max_depth {
+                continue;
+            }
+            if spaces.len() <= depth {
+                spaces.resize(depth + 1, vec![]);
+            }
+            spaces[depth].push(space);
+            if let KDTreeNode::Node {
+                l_child,
+                l_space,
+                r_child,
+                r_space,
+                ..
+            } = &self.tree[index]
+            {
+                stack.push((*r_child, depth + 1, r_space));
+                stack.push((*l_child, depth + 1, l_space));
+            }
+        }
+
+        writeln!(writer, "# KD-tree exported by kdtree-ray")?;
+        let mut nb_vertices = 0;
+        for (depth, spaces) in spaces.iter().enumerate() {
+            writeln!(writer, "g depth_{depth}")?;
+            for space in spaces {
+                write_box(writer, space, nb_vertices)?;
+                nb_vertices += 8;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Float, D: Dim> KDTree<T, D> {
+    /// Export the hierarchy of the nodes as a [Graphviz DOT](https://graphviz.org/doc/info/lang.html)
+    /// graph. Nodes are labeled with their splitting plane and leaves with the number of
+    /// shapes they contain.
+    pub fn export_dot<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "digraph kdtree {{")?;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            match &self.tree[index] {
+                KDTreeNode::Leaf { shapes } => {
+                    writeln!(
+                        writer,
+                        "  n{index} [shape=box, label=\"{} shapes\"];",
+                        shapes.len()
+                    )?;
+                }
+                KDTreeNode::Node {
+                    l_child,
+                    r_child,
+                    split,
+                    ..
+                } => {
+                    writeln!(
+                        writer,
+                        "  n{index} [label=\"{:?} = {}\"];",
+                        split.dimension, split.pos
+                    )?;
+                    writeln!(writer, "  n{index} -> n{l_child} [label=\"L\"];")?;
+                    writeln!(writer, "  n{index} -> n{r_child} [label=\"R\"];")?;
+                    stack.push(*r_child);
+                    stack.push(*l_child);
+                }
+            }
+        }
+        writeln!(writer, "}}")
+    }
+}
+
+/// Write the 8 corners and 12 edges of a box.
+/// `offset` is the number of vertices already written in the file.
+fn write_box<T: Float, W: Write>(writer: &mut W, space: &AABB<T>, offset: usize) -> io::Result<()> {
+    for i in 0..8 {
+        let x = if i & 4 == 0 { space.min.x } else { space.max.x };
+        let y = if i & 2 == 0 { space.min.y } else { space.max.y };
+        let z = if i & 1 == 0 { space.min.z } else { space.max.z };
+        writeln!(writer, "v {x} {y} {z}")?;
+    }
+    for (a, b) in BOX_EDGES {
+        // OBJ indices start at 1
+        writeln!(writer, "l {} {}", offset + a + 1, offset + b + 1)?;
+    }
+    Ok(())
+}
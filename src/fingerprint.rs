@@ -0,0 +1,86 @@
+use crate::Float;
+use crate::aabb::*;
+use crate::dim::Dim;
+use crate::kdnode::KDTreeNode;
+use crate::kdtree::KDTree;
+
+static FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+static FNV_PRIME: u64 = 0x100000001b3;
+
+/// 64-bit FNV-1a hash, fed with little-endian words so it doesn't depend on the platform.
+struct Fnv(u64);
+
+impl Fnv {
+    fn write_u64(&mut self, value: u64) {
+        for byte in value.to_le_bytes() {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    fn write_float<T: Float>(&mut self, value: T) {
+        // Adding zero turns `-0.` into `0.` to be consistent with `PartialEq`
+        self.write_u64((value + T::zero()).to_f64().unwrap().to_bits());
+    }
+
+    fn write_aabb<T: Float, D: Dim>(&mut self, aabb: &AABB<T, D>) {
+        for axis in 0..D::N {
+            self.write_float(aabb.min[axis]);
+            self.write_float(aabb.max[axis]);
+        }
+    }
+}
+
+impl<T: Float, D: Dim> KDTree<T, D> {
+    /// Compute a hash of the structure of the tree: its nodes, their bounding boxes and
+    /// splitting planes, the content of the leaves and the bounding boxes of the shapes.
+    ///
+    /// The fingerprint is stable across runs and machines, and builds are deterministic:
+    /// building the same shapes with the same costs gives the same fingerprint whatever the
+    /// number of threads.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = Fnv(FNV_OFFSET_BASIS);
+        hasher.write_usize(D::N);
+        hasher.write_aabb(&self.space);
+        hasher.write_usize(self.tree.len());
+        for node in &self.tree {
+            match node {
+                KDTreeNode::Leaf { shapes } => {
+                    hasher.write_u64(0);
+                    hasher.write_usize(shapes.len());
+                    shapes.iter().for_each(|&shape| hasher.write_usize(shape));
+                }
+                KDTreeNode::Node {
+                    l_child,
+                    l_space,
+                    r_child,
+                    r_space,
+                    split,
+                } => {
+                    hasher.write_u64(1);
+                    hasher.write_usize(split.dimension.0);
+                    hasher.write_float(split.pos);
+                    hasher.write_usize(*l_child);
+                    hasher.write_aabb(l_space);
+                    hasher.write_usize(*r_child);
+                    hasher.write_aabb(r_space);
+                }
+            }
+        }
+        hasher.write_usize(self.bounds.len());
+        self.bounds.iter().for_each(|bb| hasher.write_aabb(bb));
+        hasher.0
+    }
+}
+
+impl<T: Float, D: Dim> PartialEq for KDTree<T, D> {
+    /// Trees are equal if they have the same structure, see
+    /// [fingerprint](struct.KDTree.html#method.fingerprint). The builder configuration isn't
+    /// compared.
+    fn eq(&self, other: &Self) -> bool {
+        self.space == other.space && self.tree == other.tree && self.bounds == other.bounds
+    }
+}
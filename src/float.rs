@@ -0,0 +1,18 @@
+use std::fmt::Display;
+
+use cgmath::BaseFloat;
+use cgmath::num_traits::float::TotalOrder;
+use cgmath::num_traits::{NumCast, ToPrimitive};
+
+/// Floating point type of the coordinates and costs: `f32` (the default) or `f64`.
+///
+/// Use `f64` for scenes with large coordinates where `f32` loses precision far from the
+/// origin.
+pub trait Float: BaseFloat + TotalOrder + Display + Send + Sync + 'static {
+    /// Convert a primitive number into this type.
+    fn cast<N: ToPrimitive>(n: N) -> Self {
+        <Self as NumCast>::from(n).unwrap()
+    }
+}
+
+impl<T: BaseFloat + TotalOrder + Display + Send + Sync + 'static> Float for T {}
@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use cgmath::{Matrix4, SquareMatrix};
+
+use crate::aabb::*;
+use crate::config::BuilderConfig;
+use crate::kdtree::KDTree;
+use crate::{Float, ToVector, Vector3};
+
+/// A KD-tree placed in the scene with an affine transformation.
+///
+/// The same tree can be shared by many instances.
+#[derive(Clone, Debug)]
+pub struct Instance<T: Float = f32> {
+    tree: Arc<KDTree<T>>,
+    transform: Matrix4<T>,
+    inverse: Matrix4<T>,
+}
+
+impl<T: Float> Instance<T> {
+    /// Create a new instance of `tree` given the transformation from the space of the tree
+    /// (object space) to the space of the scene (world space).
+    ///
+    /// ### Panics
+    ///
+    /// * If `transform` is not invertible.
+    pub fn new(tree: Arc<KDTree<T>>, transform: Matrix4<T>) -> Self {
+        let inverse = transform
+            .invert()
+            .expect("instance transformation must be invertible");
+        Instance {
+            tree,
+            transform,
+            inverse,
+        }
+    }
+
+    /// Retrieve the instanced tree.
+    pub fn tree(&self) -> &Arc<KDTree<T>> {
+        &self.tree
+    }
+
+    /// Retrieve the transformation from object space to world space.
+    pub fn transform(&self) -> &Matrix4<T> {
+        &self.transform
+    }
+
+    /// Return the shapes of the instanced tree that can be intersected by a ray given in
+    /// world space.
+    pub fn intersect<V: ToVector<T>>(&self, ray_origin: &V, ray_direction: &V) -> Vec<usize> {
+        let origin = (self.inverse * ray_origin.to_vector().extend(T::one())).truncate();
+        let direction = (self.inverse * ray_direction.to_vector().extend(T::zero())).truncate();
+        self.tree.intersect(&origin, &direction)
+    }
+}
+
+impl<T: Float> Bounded<T> for Instance<T> {
+    /// Bounding box of the transformed bounding box of the tree.
+    fn bound(&self) -> AABB<T> {
+        let space = self.tree.bound();
+        let mut bound = AABB::empty();
+        for i in 0..8 {
+            let corner = Vector3::new(
+                if i & 4 == 0 { space.min.x } else { space.max.x },
+                if i & 2 == 0 { space.min.y } else { space.max.y },
+                if i & 1 == 0 { space.min.z } else { space.max.z },
+            );
+            let corner = (self.transform * corner.extend(T::one())).truncate();
+            bound.merge(&AABB::new(corner, corner));
+        }
+        bound
+    }
+}
+
+/// Two-level acceleration structure: a KD-tree of [Instance](struct.Instance.html)s.
+///
+/// Rays are transformed into the object space of each instance they can hit before
+/// descending into its tree.
+#[derive(Clone, Debug)]
+pub struct InstanceTree<T: Float = f32> {
+    instances: Vec<Instance<T>>,
+    tree: KDTree<T>,
+}
+
+impl<T: Float> InstanceTree<T> {
+    /// Build the top-level tree over the transformed bounds of the instances.
+    /// You also should give a configuration.
+    /// Panic if `instances` is empty.
+    pub fn build_config(instances: Vec<Instance<T>>, config: &BuilderConfig<T>) -> Self {
+        let tree = KDTree::build_config(&instances, config);
+        InstanceTree { instances, tree }
+    }
+
+    /// Build the top-level tree over the transformed bounds of the instances.
+    /// Take a default configuration.
+    /// Panic if `instances` is empty.
+    pub fn build(instances: Vec<Instance<T>>) -> Self {
+        Self::build_config(instances, &BuilderConfig::default())
+    }
+
+    /// Retrieve the instances of the tree.
+    pub fn instances(&self) -> &[Instance<T>] {
+        &self.instances
+    }
+
+    /// This function takes a ray and return a reduced list of `(instance, shape)` pairs
+    /// that can be intersected by the ray.
+    pub fn intersect<V: ToVector<T>>(
+        &self,
+        ray_origin: &V,
+        ray_direction: &V,
+    ) -> Vec<(usize, usize)> {
+        self.tree
+            .intersect(ray_origin, ray_direction)
+            .into_iter()
+            .flat_map(|instance| {
+                self.instances[instance]
+                    .intersect(ray_origin, ray_direction)
+                    .into_iter()
+                    .map(move |shape| (instance, shape))
+            })
+            .collect()
+    }
+}
+
+impl<T: Float> Bounded<T> for InstanceTree<T> {
+    fn bound(&self) -> AABB<T> {
+        self.tree.bound()
+    }
+}
@@ -1,26 +1,28 @@
-use std::collections::HashMap;
-
+use crate::Float;
 use crate::aabb::*;
-use crate::candidate::{Candidates, Side};
+use crate::candidate::{Candidates, EventKind, Side};
 use crate::config::BuilderConfig;
+use crate::dim::Dim;
 use crate::plane::{Dimension, Plane};
+use crate::progress::{BuildError, BuildProgress, split_share};
 
-#[derive(Clone, Debug)]
-pub enum KDTreeNode {
+#[derive(Clone, Debug, PartialEq)]
+pub enum KDTreeNode<T: Float, D: Dim, L = Vec<usize>> {
     Leaf {
-        shapes: Vec<usize>,
+        shapes: L,
     },
     Node {
         l_child: usize,
-        l_space: AABB,
+        l_space: AABB<T, D>,
         r_child: usize,
-        r_space: AABB,
+        r_space: AABB<T, D>,
+        split: Plane<T>,
     },
 }
 
-impl KDTreeNode {
+impl<T: Float, D: Dim, L> KDTreeNode<T, D, L> {
     /// Move indices of the tree by `offset`.
-    fn move_indices(&mut self, offset: usize) {
+    pub(crate) fn move_indices(&mut self, offset: usize) {
         match self {
             KDTreeNode::Leaf { .. } => {}
             KDTreeNode::Node {
@@ -31,42 +33,86 @@ impl KDTreeNode {
             }
         }
     }
+
+    /// Turn the data of a leaf into another type, keeping nodes as they are.
+    pub(crate) fn map_leaf<M, F: FnOnce(L) -> M>(self, make_leaf: F) -> KDTreeNode<T, D, M> {
+        match self {
+            KDTreeNode::Leaf { shapes } => KDTreeNode::Leaf {
+                shapes: make_leaf(shapes),
+            },
+            KDTreeNode::Node {
+                l_child,
+                l_space,
+                r_child,
+                r_space,
+                split,
+            } => KDTreeNode::Node {
+                l_child,
+                l_space,
+                r_child,
+                r_space,
+                split,
+            },
+        }
+    }
 }
 
 /// Build a KDTree from a list of candidates and return the depth of the tree.
-pub fn build_tree(
-    config: &BuilderConfig,
-    space: &AABB,
-    candidates: Candidates,
-    nb_shapes: usize,
-) -> (usize, Vec<KDTreeNode>) {
-    let (cost, best_index, n_l, n_r) = partition(config, nb_shapes, space, &candidates);
-
-    // Check that the cost of the splitting is not higher than the cost of the leaf.
-    if cost > config.cost_intersection() * nb_shapes as f32 {
-        // Create indices values vector
-        let shapes = candidates
-            .iter()
-            .filter(|e| e.is_left() && e.dimension() == Dimension::X)
-            .map(|e| e.shape)
-            .collect();
-        return (1, vec![KDTreeNode::Leaf { shapes }]);
-    }
+/// `shapes` gives the index in the tree of the shapes of the candidates and `share` the
+/// share of the node in the progress of the build.
+pub fn build_tree<T: Float, D: Dim>(
+    config: &BuilderConfig<T>,
+    progress: &BuildProgress,
+    space: &AABB<T, D>,
+    candidates: Candidates<T>,
+    shapes: Vec<usize>,
+    share: f64,
+) -> Result<(usize, Vec<KDTreeNode<T, D>>), BuildError> {
+    progress.check()?;
+    let nb_shapes = shapes.len();
+    let Some((_, split, planar_side)) = find_split(config, space, &candidates, nb_shapes) else {
+        progress.finish_leaf(share);
+        let shapes = leaf_shapes(&candidates, &shapes);
+        return Ok((1, vec![KDTreeNode::Leaf { shapes }]));
+    };
 
     // Compute the new spaces divided by `plane`
-    let (l_space, r_space) = split_space(space, &candidates[best_index].plane);
+    let (l_space, r_space) = split_space(space, &split);
 
     // Compute which candidates are part of the left and right space
-    let (left_candidates, right_candidates) = classify(candidates, best_index, nb_shapes);
+    let ((left_candidates, left_shapes), (right_candidates, right_shapes)) =
+        split_node(config, &candidates, &shapes, &split, planar_side);
+    // The children are built without the candidates of their parent
+    drop((candidates, shapes));
 
     // Add left child
-    let (left, right) = rayon::join(
-        || build_tree(config, &l_space, left_candidates, n_l),
-        || build_tree(config, &r_space, right_candidates, n_r),
+    let (l_share, r_share) = split_share(share, left_shapes.len(), right_shapes.len());
+    let (left, right) = config.join(
+        nb_shapes,
+        || {
+            build_tree(
+                config,
+                progress,
+                &l_space,
+                left_candidates,
+                left_shapes,
+                l_share,
+            )
+        },
+        || {
+            build_tree(
+                config,
+                progress,
+                &r_space,
+                right_candidates,
+                right_shapes,
+                r_share,
+            )
+        },
     );
 
-    let (depth_left, mut tree_left) = left;
-    let (depth_right, mut tree_right) = right;
+    let (depth_left, mut tree_left) = left?;
+    let (depth_right, mut tree_right) = right?;
     let mut tree = vec![];
 
     // Add current node
@@ -77,6 +123,7 @@ pub fn build_tree(
         l_space,
         r_child: r_child_index,
         r_space,
+        split,
     });
 
     // Update indices of the left tree.
@@ -91,161 +138,306 @@ pub fn build_tree(
         .for_each(|node| node.move_indices(r_child_index));
     tree.extend(tree_right);
 
-    (1 + depth_left.max(depth_right), tree)
+    Ok((1 + depth_left.max(depth_right), tree))
+}
+
+/// Compute the best split of a node, `None` if the node is better as a leaf.
+/// Return the cost of the split, the splitting plane and the side of the shapes lying in
+/// the plane.
+pub(crate) fn find_split<T: Float, D: Dim>(
+    config: &BuilderConfig<T>,
+    space: &AABB<T, D>,
+    candidates: &Candidates<T>,
+    nb_shapes: usize,
+) -> Option<(T, Plane<T>, Side)> {
+    let (cost, split, planar_side) = partition(config, nb_shapes, space, candidates);
+
+    // Check that the cost of the splitting is not higher than the cost of the leaf.
+    if cost > leaf_cost(config, nb_shapes) {
+        return None;
+    }
+    Some((cost, split, planar_side))
+}
+
+/// Cost of a leaf of `nb_shapes` shapes.
+pub(crate) fn leaf_cost<T: Float>(config: &BuilderConfig<T>, nb_shapes: usize) -> T {
+    config.cost_intersection() * T::cast(nb_shapes)
+}
+
+/// Shapes of a leaf given the candidates of its node.
+pub(crate) fn leaf_shapes<T: Float>(candidates: &Candidates<T>, shapes: &[usize]) -> Vec<usize> {
+    candidates
+        .axis(Dimension::X)
+        .iter()
+        .filter(|e| e.is_start())
+        .map(|e| shapes[e.shape as usize])
+        .collect()
 }
 
-/// Compute the best splitting candidate
+/// Candidates of a child node and the index in the tree of their shapes.
+pub(crate) type ChildContent<T> = (Candidates<T>, Vec<usize>);
+
+/// Split the candidates and the shapes of a node between its children.
+pub(crate) fn split_node<T: Float>(
+    config: &BuilderConfig<T>,
+    candidates: &Candidates<T>,
+    shapes: &[usize],
+    split: &Plane<T>,
+    planar_side: Side,
+) -> (ChildContent<T>, ChildContent<T>) {
+    let nb_shapes = shapes.len();
+    let parallel = config.is_parallel(nb_shapes);
+    let sides = classify(candidates, split, planar_side, nb_shapes);
+    let (left_shapes, right_shapes) = split_shapes(shapes, &sides);
+    let (left_candidates, right_candidates) = candidates.split(&sides, parallel);
+    (
+        (left_candidates, left_shapes),
+        (right_candidates, right_shapes),
+    )
+}
+
+/// Compute the best splitting plane by sweeping the candidates of each axis.
 /// Return:
 /// * Cost of the split
-/// * Index of the best candidate
-/// * Number of items in the left partition
-/// * Number of items in the right partition
-fn partition(
-    config: &BuilderConfig,
+/// * Splitting plane
+/// * Side of the shapes lying in the splitting plane (`Left` or `Right`)
+fn partition<T: Float, D: Dim>(
+    config: &BuilderConfig<T>,
+    n: usize,
+    space: &AABB<T, D>,
+    candidates: &Candidates<T>,
+) -> (T, Plane<T>, Side) {
+    let mut search = SplitSearch::new(config, n, space);
+    for (dim, axis) in candidates.iter() {
+        search.sweep(dim, axis.iter().map(|c| (c.pos, c.kind)));
+    }
+    search.finish()
+}
+
+/// Search of the best splitting plane of a node, fed with the sorted candidates of each
+/// axis.
+///
+/// When several planes have the same cost, the one with the lowest position (and then the
+/// lowest dimension) is chosen, so the result doesn't depend on the order of the axes.
+pub(crate) struct SplitSearch<'a, T: Float, D: Dim> {
+    config: &'a BuilderConfig<T>,
+    space: &'a AABB<T, D>,
+    /// Number of shapes in the node.
     n: usize,
-    space: &AABB,
-    candidates: &Candidates,
-) -> (f32, usize, usize, usize) {
-    let mut best_cost = f32::INFINITY;
-    let mut best_candidate_index = 0;
-
-    // Variables to keep count the number of items in both subspace for each dimension
-    let mut n_l = Dimension::get_map(0);
-    let mut n_r = Dimension::get_map(n);
-
-    // Keep n_l and n_r for the best splitting candidate
-    let mut best_n_l = 0;
-    let mut best_n_r = n;
-
-    // Find best candidate
-    for (i, candidate) in candidates.iter().enumerate() {
-        let dim = candidate.dimension();
-
-        // If the right candidate removes it from the right subspace
-        if candidate.is_right() {
-            n_r[dim] -= 1;
+    best_cost: T,
+    best_plane: Option<Plane<T>>,
+    best_planar_side: Side,
+}
+
+impl<'a, T: Float, D: Dim> SplitSearch<'a, T, D> {
+    pub fn new(config: &'a BuilderConfig<T>, n: usize, space: &'a AABB<T, D>) -> Self {
+        SplitSearch {
+            config,
+            space,
+            n,
+            best_cost: T::infinity(),
+            best_plane: None,
+            best_planar_side: Side::Left,
         }
+    }
+
+    /// Sweep the sorted candidates (position and kind) of an axis.
+    pub fn sweep(&mut self, dim: Dimension, candidates: impl Iterator<Item = (T, EventKind)>) {
+        // Number of items in both subspaces
+        let mut n_l = 0;
+        let mut n_r = self.n;
+
+        // Candidates on the same plane are processed together
+        let mut candidates = candidates.peekable();
+        while let Some((pos, kind)) = candidates.next() {
+            let plane = Plane::new(dim, pos);
+
+            // Count the events on the plane
+            let (mut n_end, mut n_planar, mut n_start) = (0, 0, 0);
+            let mut count = |kind| match kind {
+                EventKind::End => n_end += 1,
+                EventKind::Planar => n_planar += 1,
+                EventKind::Start => n_start += 1,
+            };
+            count(kind);
+            while let Some((_, kind)) = candidates.next_if(|(other, _)| *other == pos) {
+                count(kind);
+            }
+
+            // Shapes ending or lying in the plane are removed from the right subspace
+            n_r -= n_end + n_planar;
+
+            // Compute the cost of the split with the planar shapes on the cheapest side
+            let mut cost_split = cost(self.config, &plane, self.space, n_l + n_planar, n_r);
+            let mut planar_side = Side::Left;
+            if n_planar > 0 {
+                let cost_right = cost(self.config, &plane, self.space, n_l, n_r + n_planar);
+                if cost_right < cost_split {
+                    cost_split = cost_right;
+                    planar_side = Side::Right;
+                }
+            }
 
-        // Compute the cost of the split and update the best split
-        let cost = cost(config, &candidate.plane, space, n_l[dim], n_r[dim]);
-        if cost < best_cost {
-            best_cost = cost;
-            best_candidate_index = i;
-            best_n_l = n_l[dim];
-            best_n_r = n_r[dim];
+            // Update the best split
+            let is_best = cost_split < self.best_cost
+                || (cost_split == self.best_cost
+                    && self.best_plane.as_ref().is_some_and(|best| {
+                        plane
+                            .pos
+                            .total_cmp(&best.pos)
+                            .then(dim.0.cmp(&best.dimension.0))
+                            .is_lt()
+                    }));
+            if is_best {
+                self.best_cost = cost_split;
+                self.best_plane = Some(plane);
+                self.best_planar_side = planar_side;
+            }
+
+            // Shapes starting or lying in the plane are added to the left subspace
+            n_l += n_start + n_planar;
         }
+    }
 
-        // If the left candidate add it from the left subspace
-        if candidate.is_left() {
-            n_l[dim] += 1;
+    /// Return the cost of the best split, its plane and the side of the shapes lying in
+    /// the plane.
+    pub fn finish(self) -> (T, Plane<T>, Side) {
+        match self.best_plane {
+            Some(plane) => (self.best_cost, plane, self.best_planar_side),
+            None => (
+                T::infinity(),
+                Plane::new(Dimension::X, T::zero()),
+                Side::Left,
+            ),
         }
     }
-    (best_cost, best_candidate_index, best_n_l, best_n_r)
 }
 
 /// Split an AABB in two subspaces given a splitting plane
-fn split_space(space: &AABB, splitting_plane: &Plane) -> (AABB, AABB) {
+pub(crate) fn split_space<T: Float, D: Dim>(
+    space: &AABB<T, D>,
+    splitting_plane: &Plane<T>,
+) -> (AABB<T, D>, AABB<T, D>) {
     let mut left = space.clone();
     let mut right = space.clone();
     let pos = splitting_plane.pos;
-    match splitting_plane.dimension {
-        Dimension::X => {
-            right.min.x = pos.clamp(space.min.x, space.max.x);
-            left.max.x = pos.clamp(space.min.x, space.max.x);
-        }
-        Dimension::Y => {
-            right.min.y = pos.clamp(space.min.y, space.max.y);
-            left.max.y = pos.clamp(space.min.y, space.max.y);
-        }
-        Dimension::Z => {
-            right.min.z = pos.clamp(space.min.z, space.max.z);
-            left.max.z = pos.clamp(space.min.z, space.max.z);
-        }
-    }
+    let axis = splitting_plane.dimension.0;
+    right.min[axis] = pos.max(space.min[axis]).min(space.max[axis]);
+    left.max[axis] = right.min[axis];
     (left, right)
 }
 
-fn classify(
-    candidates: Candidates,
-    best_index: usize,
+/// Given the candidates of a node and a splitting plane identify which items are part of
+/// the left, right and both subspaces.
+fn classify<T: Float>(
+    candidates: &Candidates<T>,
+    split: &Plane<T>,
+    planar_side: Side,
     nb_shapes: usize,
-) -> (Candidates, Candidates) {
-    let mut sides = HashMap::with_capacity(nb_shapes);
-    // Step 1: Udate sides to classify items
-    classify_items(&candidates, best_index, &mut sides);
-
-    // Step 2: Splicing candidates left and right subspace
-    splicing_candidates(candidates, &sides)
-}
-
-/// Step 1 of classify.
-/// Given a candidate list and a splitting candidate identify wich items are part of the
-/// left, right and both subspaces.
-fn classify_items(candidates: &Candidates, best_index: usize, sides: &mut HashMap<usize, Side>) {
-    let best_dimension = candidates[best_index].dimension();
-    (0..(best_index + 1)).for_each(|i| {
-        if candidates[i].dimension() == best_dimension {
-            if candidates[i].is_right() {
-                sides.insert(candidates[i].shape, Side::Left);
-            } else {
-                sides.insert(candidates[i].shape, Side::Both);
-            }
-        }
-    });
-    (best_index..candidates.len()).for_each(|i| {
-        if candidates[i].dimension() == best_dimension && candidates[i].is_left() {
-            sides.insert(candidates[i].shape, Side::Right);
+) -> Vec<Side> {
+    let mut sides = vec![Side::Both; nb_shapes];
+    for candidate in candidates.axis(split.dimension) {
+        if let Some(side) = classify_item(candidate.pos, candidate.kind, split, planar_side) {
+            sides[candidate.shape as usize] = side;
         }
-    });
+    }
+    sides
 }
 
-// Step 2: Splicing candidates left and right subspace given items sides
-fn splicing_candidates(
-    mut candidates: Candidates,
-    sides: &HashMap<usize, Side>,
-) -> (Candidates, Candidates) {
-    let mut left_candidates = Candidates::with_capacity(candidates.len() / 2);
-    let mut right_candidates = Candidates::with_capacity(candidates.len() / 2);
+/// Side of the shape of a candidate (in the splitting dimension) if the candidate is
+/// enough to know it. Each shape has at most one such candidate.
+pub(crate) fn classify_item<T: Float>(
+    pos: T,
+    kind: EventKind,
+    split: &Plane<T>,
+    planar_side: Side,
+) -> Option<Side> {
+    let side = match kind {
+        EventKind::End if pos <= split.pos => Side::Left,
+        EventKind::Start if pos >= split.pos => Side::Right,
+        EventKind::Planar if pos < split.pos => Side::Left,
+        EventKind::Planar if pos > split.pos => Side::Right,
+        EventKind::Planar => planar_side,
+        _ => return None,
+    };
+    Some(side)
+}
+
+/// Side of a splitting plane a shape lies on, as [classify_item] puts it in the children.
+/// Return `None` for a shape flat on the plane, which can be on either side.
+pub(crate) fn shape_side<T: Float, D: Dim>(bb: &AABB<T, D>, split: &Plane<T>) -> Option<Side> {
+    let (min, max) = split.axis_bounds(bb);
+    if min == split.pos && max == split.pos {
+        None
+    } else if max <= split.pos {
+        Some(Side::Left)
+    } else if min >= split.pos {
+        Some(Side::Right)
+    } else {
+        Some(Side::Both)
+    }
+}
 
-    for e in candidates.drain(..) {
-        match sides[&e.shape] {
-            Side::Left => left_candidates.push(e),
-            Side::Right => right_candidates.push(e),
+/// Split the shapes of a node between its children given their sides.
+fn split_shapes(shapes: &[usize], sides: &[Side]) -> (Vec<usize>, Vec<usize>) {
+    let mut left_shapes = vec![];
+    let mut right_shapes = vec![];
+    for (&shape, side) in shapes.iter().zip(sides) {
+        match side {
+            Side::Left => left_shapes.push(shape),
+            Side::Right => right_shapes.push(shape),
             Side::Both => {
-                right_candidates.push(e.clone());
-                left_candidates.push(e);
+                left_shapes.push(shape);
+                right_shapes.push(shape);
             }
         }
     }
-    (left_candidates, right_candidates)
+    (left_shapes, right_shapes)
 }
 
 /// Surface Area Heuristic (SAH)
-fn cost(config: &BuilderConfig, plane: &Plane, space: &AABB, n_left: usize, n_right: usize) -> f32 {
+fn cost<T: Float, D: Dim>(
+    config: &BuilderConfig<T>,
+    plane: &Plane<T>,
+    space: &AABB<T, D>,
+    n_left: usize,
+    n_right: usize,
+) -> T {
     // If the plane doesn't cut the space, return max cost
     if !plane.is_cutting(space) {
-        return f32::INFINITY;
+        return T::infinity();
     }
 
     // Compute the surface area of the whole space
     let surface_space = space.surface();
 
-    // Split space
-    let (space_left, space_right) = split_space(space, plane);
+    // Compute the probability to hit each subspace
+    let (ratio_left, ratio_right) = if surface_space > T::zero() {
+        // Split space
+        let (space_left, space_right) = split_space(space, plane);
 
-    // Compute the surface area of both subspace
-    let surface_left = space_left.surface();
-    let surface_right = space_right.surface();
+        // Compute the surface area of both subspace
+        (
+            space_left.surface() / surface_space,
+            space_right.surface() / surface_space,
+        )
+    } else {
+        // The space has no surface (e.g. aligned points), use lengths along the axis instead
+        let (min, max) = plane.axis_bounds(space);
+        (
+            (plane.pos - min) / (max - min),
+            (max - plane.pos) / (max - min),
+        )
+    };
 
     // Compute raw cost
     let cost = config.cost_traversal()
         + config.cost_intersection()
-            * (n_left as f32 * surface_left / surface_space
-                + n_right as f32 * surface_right / surface_space);
+            * (T::cast(n_left) * ratio_left + T::cast(n_right) * ratio_right);
 
     // Decrease cost if it cuts empty space
     if n_left == 0 || n_right == 0 {
-        cost * (1. - config.empty_cut_bonus())
+        cost * (T::one() - config.empty_cut_bonus())
     } else {
         cost
     }
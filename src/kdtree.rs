@@ -1,93 +1,387 @@
-use rayon::ThreadPoolBuilder;
-
-use crate::Vector3;
 use crate::aabb::*;
 use crate::candidate::*;
 use crate::config::BuilderConfig;
+use crate::dim::{D2, D3, Dim};
 use crate::kdnode::{KDTreeNode, build_tree};
+use crate::leaf::LeafData;
+use crate::progress::{BuildError, BuildProgress};
 use crate::ray::Ray;
+use crate::stats::{QueryCounter, QueryStats};
+use crate::{Float, ToVector};
+
+/// KD-tree of 2D shapes (rectangles) queried with 2D rays.
+pub type KDTree2<T = f32> = KDTree<T, D2>;
+
+/// KD-tree of 3D shapes (boxes) queried with 3D rays.
+pub type KDTree3<T = f32> = KDTree<T, D3>;
 
 /// The KD-tree data structure.
+///
+/// It's generic over the float type `T` and the dimension `D` of the space, see
+/// [KDTree2](type.KDTree2.html) and [KDTree3](type.KDTree3.html), and over the data `L`
+/// stored in its leaves, see [LeafData](trait.LeafData.html).
+///
+/// Trees storing indices in their leaves (the default) can be edited, refitted, reordered
+/// and saved. Trees with other leaf data can only be queried with
+/// [intersect_leaves](#method.intersect_leaves).
 #[derive(Clone, Debug)]
-pub struct KDTree {
-    tree: Vec<KDTreeNode>,
-    space: AABB,
-    depth: usize,
+pub struct KDTree<T: Float = f32, D: Dim = D3, L: LeafData = Vec<usize>> {
+    pub(crate) tree: Vec<KDTreeNode<T, D, L>>,
+    pub(crate) space: AABB<T, D>,
+    pub(crate) depth: usize,
+    /// Costs of the build, used by edits in the current thread pool.
+    pub(crate) config: BuilderConfig<T>,
+    /// Bounding box of every shape in the tree (empty for removed shapes).
+    pub(crate) bounds: Vec<AABB<T, D>>,
+    /// SAH cost right after the last full build.
+    pub(crate) reference_cost: T,
+    /// Whether leaves can reference shapes that don't lie in their region anymore, after a
+    /// [refit](#method.refit).
+    pub(crate) stale_references: bool,
 }
 
-impl KDTree {
+impl<T: Float, D: Dim> KDTree<T, D> {
     /// This function is used to build a new KD-tree. You need to provide a
     /// `Vec` of shapes that implement `Bounded` trait.
     /// You also should give a configuration.
+    /// Panic if the `shapes` is empty or if the build is cancelled, see
+    /// [try_build_config](#method.try_build_config).
+    pub fn build_config<S: Bounded<T, D>>(shapes: &[S], config: &BuilderConfig<T>) -> Self {
+        Self::try_build_config(shapes, config).unwrap()
+    }
+
+    /// Same as [build_config](#method.build_config) but return an error instead of a tree
+    /// if the build is cancelled with the
+    /// [cancellation token](struct.BuilderConfig.html#method.with_cancellation) of `config`.
     /// Panic if the `shapes` is empty.
-    pub fn build_config<S: Bounded>(shapes: &[S], config: &BuilderConfig) -> Self {
+    pub fn try_build_config<S: Bounded<T, D>>(
+        shapes: &[S],
+        config: &BuilderConfig<T>,
+    ) -> Result<Self, BuildError> {
         assert!(!shapes.is_empty());
-        let mut space = AABB::default();
-        let mut candidates = Candidates::with_capacity(shapes.len() * 6);
-        for (index, v) in shapes.iter().enumerate() {
-            // Create items from values
-            let bb = v.bound();
-            candidates.extend(Candidate::gen_candidates(index, &bb));
-
-            // Update space with the bounding box of the item
-            space.merge(&bb);
-        }
-
-        // Sort candidates only once at the begining
-        candidates.sort();
-
-        let nb_shapes = shapes.len();
-
-        // Build the tree
-        let pool = ThreadPoolBuilder::new().build().unwrap();
-        let (depth, tree) = pool.install(|| build_tree(config, &space, candidates, nb_shapes));
-
-        KDTree { space, tree, depth }
+        let bounds = shapes.iter().map(|s| s.bound()).collect();
+        Self::try_build_bounds(bounds, config)
     }
 
     /// This function is used to build a new KD-tree. You need to provide a
     /// `Vec` of shapes that implement `Bounded` trait.
     /// Take a default configuration.
     /// Panic if the `shapes` is empty.
-    pub fn build<S: Bounded>(shapes: &[S]) -> Self {
+    pub fn build<S: Bounded<T, D>>(shapes: &[S]) -> Self {
         Self::build_config(shapes, &BuilderConfig::default())
     }
 
+    /// Build a KD-tree from the bounding boxes of the shapes, the index of a shape is its
+    /// position in `bounds`. Take a default configuration.
+    /// Panic if `bounds` is empty.
+    ///
+    /// ```
+    /// use cgmath::Vector3;
+    /// use kdtree_ray::{AABB, KDTree};
+    ///
+    /// let boxes: Vec<AABB> = (0..10)
+    ///     .map(|i| {
+    ///         let min = Vector3::new(i as f32, 0., 0.);
+    ///         AABB::new(min, min + Vector3::new(1., 1., 1.))
+    ///     })
+    ///     .collect();
+    /// let tree = KDTree::from_bounds(boxes.iter().cloned());
+    ///
+    /// // `AABB`s, references, boxes and `Arc`s of shapes are `Bounded` too
+    /// assert!(tree == KDTree::build(&boxes));
+    /// assert!(tree == KDTree::build_iter(&boxes));
+    /// assert!(tree == KDTree::build_iter(boxes.into_iter().map(Box::new)));
+    /// ```
+    pub fn from_bounds<I: IntoIterator<Item = AABB<T, D>>>(bounds: I) -> Self {
+        Self::from_bounds_config(bounds, &BuilderConfig::default())
+    }
+
+    /// Same as [from_bounds](#method.from_bounds) with a configuration.
+    /// Panic if `bounds` is empty or if the build is cancelled.
+    pub fn from_bounds_config<I: IntoIterator<Item = AABB<T, D>>>(
+        bounds: I,
+        config: &BuilderConfig<T>,
+    ) -> Self {
+        let bounds: Vec<_> = bounds.into_iter().collect();
+        assert!(!bounds.is_empty());
+        Self::try_build_bounds(bounds, config).unwrap()
+    }
+
+    /// Same as [build_config](#method.build_config) with the shapes given by an iterator
+    /// of known size, so they don't have to be collected into a slice first.
+    /// Panic if `shapes` is empty or if the build is cancelled.
+    pub fn build_iter_config<I>(shapes: I, config: &BuilderConfig<T>) -> Self
+    where
+        I: IntoIterator,
+        I::IntoIter: ExactSizeIterator,
+        I::Item: Bounded<T, D>,
+    {
+        let shapes = shapes.into_iter();
+        assert!(shapes.len() > 0);
+        let mut bounds = Vec::with_capacity(shapes.len());
+        bounds.extend(shapes.map(|s| s.bound()));
+        Self::try_build_bounds(bounds, config).unwrap()
+    }
+
+    /// Same as [build_iter_config](#method.build_iter_config) with a default
+    /// configuration.
+    pub fn build_iter<I>(shapes: I) -> Self
+    where
+        I: IntoIterator,
+        I::IntoIter: ExactSizeIterator,
+        I::Item: Bounded<T, D>,
+    {
+        Self::build_iter_config(shapes, &BuilderConfig::default())
+    }
+
+    /// Build a KD-tree given the bounding boxes of the shapes.
+    fn try_build_bounds(
+        bounds: Vec<AABB<T, D>>,
+        config: &BuilderConfig<T>,
+    ) -> Result<Self, BuildError> {
+        let progress = BuildProgress::new(config);
+        progress.check()?;
+        let space = AABB::enclosing(&bounds);
+        let nb_shapes = bounds.len();
+
+        // Build the tree
+        let (depth, tree) = config.threading().install(|| {
+            // Sort candidates only once at the begining
+            let parallel = config.is_parallel(nb_shapes);
+            let candidates = Candidates::new(&bounds, parallel);
+            let shapes = (0..nb_shapes).collect();
+            build_tree(config, &progress, &space, candidates, shapes, 1.)
+        })?;
+        progress.finish();
+
+        let mut kdtree = KDTree {
+            space,
+            tree,
+            depth,
+            config: config.for_edits(),
+            bounds,
+            reference_cost: T::zero(),
+            stale_references: false,
+        };
+        kdtree.reference_cost = kdtree.sah_cost();
+        Ok(kdtree)
+    }
+
     /// This function takes a ray and return a reduced list of shapes that
     /// can be intersected by the ray.
-    pub fn intersect(&self, ray_origin: &Vector3, ray_direction: &Vector3) -> Vec<usize> {
+    pub fn intersect<V: ToVector<T, D>>(&self, ray_origin: &V, ray_direction: &V) -> Vec<usize> {
         let ray = Ray::new(ray_origin, ray_direction);
+        self.intersect_counted(&ray, &mut ())
+    }
+
+    /// Same as [intersect](#method.intersect) but also accumulates traversal counters
+    /// into `stats`.
+    pub fn intersect_with_stats<V: ToVector<T, D>>(
+        &self,
+        ray_origin: &V,
+        ray_direction: &V,
+        stats: &mut QueryStats,
+    ) -> Vec<usize> {
+        let ray = Ray::new(ray_origin, ray_direction);
+        self.intersect_counted(&ray, stats)
+    }
+
+    fn intersect_counted<C: QueryCounter>(&self, ray: &Ray<T, D>, counter: &mut C) -> Vec<usize> {
         let mut result = vec![];
-        let mut stack = vec![0];
-        stack.reserve_exact(self.depth);
-        while !stack.is_empty() {
-            let node = &self.tree[stack.pop().unwrap()];
-            match node {
-                KDTreeNode::Leaf { shapes } => result.extend(shapes),
+        self.visit_leaves(ray, counter, &mut |shapes: &Vec<usize>, counter: &mut C| {
+            counter.visit_leaf(shapes.len());
+            result.extend(shapes)
+        });
+        dedup_shapes(&mut result);
+        result
+    }
+
+    /// Turn the indices of the shapes of each leaf into another leaf data, keeping the
+    /// same nodes.
+    pub fn map_leaves<L: LeafData, F: FnMut(Vec<usize>) -> L>(
+        self,
+        mut make_leaf: F,
+    ) -> KDTree<T, D, L> {
+        KDTree {
+            tree: self
+                .tree
+                .into_iter()
+                .map(|node| node.map_leaf(&mut make_leaf))
+                .collect(),
+            space: self.space,
+            depth: self.depth,
+            config: self.config,
+            bounds: self.bounds,
+            reference_cost: self.reference_cost,
+            stale_references: self.stale_references,
+        }
+    }
+}
+
+impl<T: Float, D: Dim, L: LeafData> KDTree<T, D, L> {
+    /// Build a KD-tree storing its own data in the leaves. `make_leaf` turns the indices
+    /// of the shapes of each leaf into the data of the leaf.
+    /// Panic if the `shapes` is empty or if the build is cancelled.
+    ///
+    /// ```
+    /// use cgmath::Vector3;
+    /// use kdtree_ray::{AABB, BuilderConfig, KDTree, LeafData};
+    ///
+    /// /// Indices of the shapes with the total size of their boxes.
+    /// struct SizedLeaf {
+    ///     shapes: Vec<usize>,
+    ///     size: f32,
+    /// }
+    ///
+    /// impl LeafData for SizedLeaf {}
+    ///
+    /// let boxes: Vec<AABB> = (0..10)
+    ///     .map(|i| {
+    ///         let min = Vector3::new(i as f32, 0., 0.);
+    ///         AABB::new(min, min + Vector3::new(0.5, 1., 1.))
+    ///     })
+    ///     .collect();
+    /// let tree = KDTree::build_with_leaves(&boxes, &BuilderConfig::default(), |shapes| {
+    ///     let size = shapes.iter().map(|&i| boxes[i].volume()).sum();
+    ///     SizedLeaf { shapes, size }
+    /// });
+    ///
+    /// let mut size = 0.;
+    /// let mut shapes: Vec<usize> = vec![];
+    /// let origin = Vector3::new(2.2, -1., 0.5);
+    /// let direction = Vector3::new(0., 1., 0.);
+    /// tree.intersect_leaves(&origin, &direction, |leaf: &SizedLeaf| {
+    ///     size += leaf.size;
+    ///     shapes.extend(&leaf.shapes);
+    /// });
+    /// assert!(size >= 0.5);
+    /// shapes.sort();
+    /// shapes.dedup();
+    /// assert_eq!(shapes, KDTree::build(&boxes).intersect(&origin, &direction));
+    /// ```
+    pub fn build_with_leaves<S: Bounded<T, D>, F: FnMut(Vec<usize>) -> L>(
+        shapes: &[S],
+        config: &BuilderConfig<T>,
+        make_leaf: F,
+    ) -> Self {
+        KDTree::build_config(shapes, config).map_leaves(make_leaf)
+    }
+
+    /// Call `visit` with the data of every leaf that can be intersected by a ray.
+    /// A shape referenced by several leaves can be visited several times.
+    pub fn intersect_leaves<V: ToVector<T, D>, F: FnMut(&L)>(
+        &self,
+        ray_origin: &V,
+        ray_direction: &V,
+        mut visit: F,
+    ) {
+        let ray = Ray::new(ray_origin, ray_direction);
+        self.visit_leaves(&ray, &mut (), &mut |leaf: &L, _: &mut ()| visit(leaf));
+    }
+
+    fn visit_leaves<C: QueryCounter, F: FnMut(&L, &mut C)>(
+        &self,
+        ray: &Ray<T, D>,
+        counter: &mut C,
+        visit: &mut F,
+    ) {
+        self.visit_leaves_with(counter, |_, space| ray.intersect(space), visit);
+    }
+
+    /// Call `visit` with the data of every leaf reached by a traversal entering the
+    /// children for which `enter(child, space)` is true. `visit` counts the shapes of the
+    /// leaves into `counter`.
+    pub(crate) fn visit_leaves_with<C, E, F>(&self, counter: &mut C, mut enter: E, visit: &mut F)
+    where
+        C: QueryCounter,
+        E: FnMut(usize, &AABB<T, D>) -> bool,
+        F: FnMut(&L, &mut C),
+    {
+        traverse(0, self.depth, |index| {
+            counter.visit_node();
+            match &self.tree[index] {
+                KDTreeNode::Leaf { shapes } => {
+                    visit(shapes, counter);
+                    [None, None]
+                }
+                KDTreeNode::Node {
+                    l_child,
+                    l_space,
+                    r_child,
+                    r_space,
+                    ..
+                } => {
+                    counter.test_aabb();
+                    let right = enter(*r_child, r_space).then_some(*r_child);
+                    counter.test_aabb();
+                    let left = enter(*l_child, l_space).then_some(*l_child);
+                    [left, right]
+                }
+            }
+        });
+    }
+
+    /// Call `visit` with the data of the leaves hit by a ray, from the nearest to the
+    /// farthest along the ray. `visit` returns the distance of the closest hit found so
+    /// far and the traversal stops before the first node entered beyond it.
+    pub(crate) fn visit_leaves_in_order<F: FnMut(&L) -> T>(&self, ray: &Ray<T, D>, mut visit: F) {
+        let Some(entry) = ray.entry(&self.space) else {
+            return;
+        };
+        let mut closest = T::infinity();
+        let mut stack = Vec::with_capacity(self.depth + 1);
+        stack.push((0, entry));
+        // Child spaces don't overlap, so nodes are popped by increasing entry distance
+        while let Some((index, entry)) = stack.pop() {
+            if entry > closest {
+                break;
+            }
+            match &self.tree[index] {
+                KDTreeNode::Leaf { shapes } => closest = closest.min(visit(shapes)),
                 KDTreeNode::Node {
                     l_child,
                     l_space,
                     r_child,
                     r_space,
+                    ..
                 } => {
-                    if ray.intersect(r_space) {
-                        stack.push(*r_child)
-                    }
-                    if ray.intersect(l_space) {
-                        stack.push(*l_child)
-                    }
+                    let left = ray.entry(l_space).map(|entry| (*l_child, entry));
+                    let right = ray.entry(r_space).map(|entry| (*r_child, entry));
+                    let (near, far) = match (left, right) {
+                        (Some(left), Some(right)) if right.1 < left.1 => (right, Some(left)),
+                        (Some(left), right) => (left, right),
+                        (None, Some(right)) => (right, None),
+                        (None, None) => continue,
+                    };
+                    stack.extend(far);
+                    stack.push(near);
                 }
             }
         }
-        // Dedup duplicated shapes
-        result.sort();
-        result.dedup();
-        result
     }
 }
 
-impl Bounded for KDTree {
-    fn bound(&self) -> AABB {
+/// Depth-first traversal from `root`: `expand` visits a node and returns its left and
+/// right children to visit next, if any.
+pub(crate) fn traverse<N, F: FnMut(N) -> [Option<N>; 2]>(root: N, depth: usize, mut expand: F) {
+    let mut stack = Vec::with_capacity(depth + 1);
+    stack.push(root);
+    while let Some(node) = stack.pop() {
+        let [left, right] = expand(node);
+        stack.extend(right);
+        stack.extend(left);
+    }
+}
+
+/// Sort the shapes found by a query and remove the duplicates, shapes can be referenced by
+/// several leaves.
+pub(crate) fn dedup_shapes(shapes: &mut Vec<usize>) {
+    shapes.sort();
+    shapes.dedup();
+}
+
+impl<T: Float, D: Dim, L: LeafData> Bounded<T, D> for KDTree<T, D, L> {
+    fn bound(&self) -> AABB<T, D> {
         self.space.clone()
     }
 }
@@ -0,0 +1,394 @@
+use std::cell::UnsafeCell;
+use std::mem;
+use std::sync::OnceLock;
+
+use crate::aabb::*;
+use crate::candidate::{Candidates, Side};
+use crate::config::{BuilderConfig, Threading};
+use crate::dim::{D3, Dim};
+use crate::kdnode::{ChildContent, KDTreeNode, find_split, leaf_shapes, split_node, split_space};
+use crate::kdtree::{KDTree, dedup_shapes, traverse};
+use crate::plane::Plane;
+use crate::ray::Ray;
+use crate::{Float, ToVector};
+
+/// Number of levels built at once: up front for the top of the tree, and then each time a
+/// query enters a region not built yet.
+static LAZY_DEPTH: usize = 8;
+
+/// KD-tree whose subtrees are built the first time a query enters them.
+///
+/// Only the top levels of the tree are built up front, the regions below keep their split
+/// candidates until a ray reaches them. Regions are built once even with concurrent
+/// queries. Scenes where most of the geometry is never hit are much faster to build.
+///
+/// The tree is the same as the one built by
+/// [KDTree::build_config](struct.KDTree.html#method.build_config) once fully built, see
+/// [into_kdtree](#method.into_kdtree).
+///
+/// **Note**: Regions are built on the thread of the query entering them, without using
+/// the threads of the configuration. Lazy builds don't report their progress and can't be
+/// cancelled: the progress callback and the cancellation token of the configuration are
+/// ignored and not kept by the tree.
+#[derive(Debug)]
+pub struct LazyKDTree<T: Float = f32, D: Dim = D3> {
+    root: Vec<LazyNode<T, D>>,
+    space: AABB<T, D>,
+    config: BuilderConfig<T>,
+    /// Configuration used to build the regions entered by queries.
+    region_config: BuilderConfig<T>,
+    bounds: Vec<AABB<T, D>>,
+}
+
+impl<T: Float, D: Dim> LazyKDTree<T, D> {
+    /// Build the top levels of a lazy KD-tree given the shapes and a configuration.
+    /// Panic if the `shapes` is empty.
+    pub fn build_config<S: Bounded<T, D>>(shapes: &[S], config: &BuilderConfig<T>) -> Self {
+        assert!(!shapes.is_empty());
+        let bounds: Vec<AABB<T, D>> = shapes.iter().map(|s| s.bound()).collect();
+        let space = AABB::enclosing(&bounds);
+
+        let nb_shapes = shapes.len();
+        let root = config.threading().install(|| {
+            let parallel = config.is_parallel(nb_shapes);
+            let candidates = Candidates::new(&bounds, parallel);
+            build_levels(config, &space, candidates, (0..nb_shapes).collect(), 0)
+        });
+
+        // Queries can run in a thread pool, regions are built without waiting for it
+        let region_config = config.for_edits().with_threading(Threading::Sequential);
+        LazyKDTree {
+            root,
+            space,
+            config: config.for_edits(),
+            region_config,
+            bounds,
+        }
+    }
+
+    /// Build the top levels of a lazy KD-tree given the shapes.
+    /// Take a default configuration.
+    /// Panic if the `shapes` is empty.
+    pub fn build<S: Bounded<T, D>>(shapes: &[S]) -> Self {
+        Self::build_config(shapes, &BuilderConfig::default())
+    }
+
+    /// Same as [KDTree::intersect](struct.KDTree.html#method.intersect), building the
+    /// regions entered by the ray.
+    pub fn intersect<V: ToVector<T, D>>(&self, ray_origin: &V, ray_direction: &V) -> Vec<usize> {
+        let ray = Ray::new(ray_origin, ray_direction);
+        let mut result = vec![];
+        traverse(
+            (self.root.as_slice(), 0),
+            LAZY_DEPTH,
+            |(nodes, index)| match &nodes[index] {
+                LazyNode::Leaf { shapes } => {
+                    result.extend(shapes);
+                    [None, None]
+                }
+                LazyNode::Node {
+                    l_child,
+                    l_space,
+                    r_child,
+                    r_space,
+                    ..
+                } => [
+                    ray.intersect(l_space).then_some((nodes, *l_child)),
+                    ray.intersect(r_space).then_some((nodes, *r_child)),
+                ],
+                LazyNode::Deferred(region) => [Some((region.nodes(&self.region_config), 0)), None],
+            },
+        );
+        dedup_shapes(&mut result);
+        result
+    }
+
+    /// Build every region not built yet.
+    pub fn materialize(&self) {
+        let mut stack = vec![self.root.as_slice()];
+        while let Some(nodes) = stack.pop() {
+            for node in nodes {
+                if let LazyNode::Deferred(region) = node {
+                    stack.push(region.nodes(&self.region_config));
+                }
+            }
+        }
+    }
+
+    /// Check if every region is built.
+    pub fn is_materialized(&self) -> bool {
+        let mut stack = vec![self.root.as_slice()];
+        while let Some(nodes) = stack.pop() {
+            for node in nodes {
+                if let LazyNode::Deferred(region) = node {
+                    match region.nodes.get() {
+                        Some(nodes) => stack.push(nodes),
+                        None => return false,
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Build every region not built yet and turn the tree into a `KDTree`, to save it
+    /// with [KDTree::write_to](struct.KDTree.html#method.write_to) for example.
+    pub fn into_kdtree(self) -> KDTree<T, D> {
+        let (tree, depth) = flatten(self.root, &self.region_config);
+        let mut kdtree = KDTree {
+            tree,
+            space: self.space,
+            depth,
+            config: self.config,
+            bounds: self.bounds,
+            reference_cost: T::zero(),
+            stale_references: false,
+        };
+        kdtree.reference_cost = kdtree.sah_cost();
+        kdtree
+    }
+}
+
+impl<T: Float, D: Dim> Bounded<T, D> for LazyKDTree<T, D> {
+    fn bound(&self) -> AABB<T, D> {
+        self.space.clone()
+    }
+}
+
+/// Node of a lazy KD-tree. Child indices are relative to the nodes of the same region.
+#[derive(Debug)]
+enum LazyNode<T: Float, D: Dim> {
+    Leaf {
+        shapes: Vec<usize>,
+    },
+    Node {
+        l_child: usize,
+        l_space: AABB<T, D>,
+        r_child: usize,
+        r_space: AABB<T, D>,
+        split: Plane<T>,
+    },
+    /// Subtree built on demand.
+    Deferred(Box<Region<T, D>>),
+}
+
+/// Region of a lazy KD-tree built the first time it's entered.
+#[derive(Debug)]
+struct Region<T: Float, D: Dim> {
+    space: AABB<T, D>,
+    /// Candidates and shapes of the region, dropped once it's built. Only the query
+    /// building the region accesses them, see `nodes`.
+    content: UnsafeCell<Option<ChildContent<T>>>,
+    /// Top levels of the region, its root first.
+    nodes: OnceLock<Vec<LazyNode<T, D>>>,
+}
+
+// SAFETY: `content` is only accessed by the initializer of `nodes`, and `OnceLock` runs a
+// single initializer at a time.
+unsafe impl<T: Float, D: Dim> Sync for Region<T, D> {}
+
+impl<T: Float, D: Dim> Region<T, D> {
+    /// Retrieve the nodes of the region, building them if needed.
+    ///
+    /// The content is only dropped once the build succeeded, a query panicking while
+    /// building the region leaves it to the next query.
+    fn nodes(&self, config: &BuilderConfig<T>) -> &[LazyNode<T, D>] {
+        self.nodes.get_or_init(|| {
+            // SAFETY: Only one query runs this initializer at a time
+            let content = unsafe { &mut *self.content.get() };
+            let guard = ContentGuard {
+                content: Some(content.take().expect("region already built")),
+                slot: content,
+            };
+            let (candidates, shapes) = guard.content.as_ref().unwrap();
+            let nodes = match split_level(config, &self.space, candidates, shapes) {
+                Ok(level) => build_children(config, level, 0),
+                Err(shapes) => vec![LazyNode::Leaf { shapes }],
+            };
+            guard.release();
+            nodes
+        })
+    }
+}
+
+/// Content taken out of a region while building it, put back if the build panics.
+struct ContentGuard<'a, T: Float> {
+    content: Option<ChildContent<T>>,
+    slot: &'a mut Option<ChildContent<T>>,
+}
+
+impl<T: Float> ContentGuard<'_, T> {
+    /// Drop the content once the region is built.
+    fn release(mut self) {
+        self.content = None;
+    }
+}
+
+impl<T: Float> Drop for ContentGuard<'_, T> {
+    fn drop(&mut self) {
+        if let Some(content) = self.content.take() {
+            *self.slot = Some(content);
+        }
+    }
+}
+
+/// Build the top levels of a subtree, as `build_tree` does, and defer the others.
+fn build_levels<T: Float, D: Dim>(
+    config: &BuilderConfig<T>,
+    space: &AABB<T, D>,
+    candidates: Candidates<T>,
+    shapes: Vec<usize>,
+    depth: usize,
+) -> Vec<LazyNode<T, D>> {
+    if depth == LAZY_DEPTH {
+        let region = Region {
+            space: space.clone(),
+            content: UnsafeCell::new(Some((candidates, shapes))),
+            nodes: OnceLock::new(),
+        };
+        return vec![LazyNode::Deferred(Box::new(region))];
+    }
+
+    match split_level(config, space, &candidates, &shapes) {
+        Ok(level) => {
+            // The children are built without the candidates of their parent
+            drop((candidates, shapes));
+            build_children(config, level, depth)
+        }
+        Err(shapes) => vec![LazyNode::Leaf { shapes }],
+    }
+}
+
+/// Split of a node: its number of shapes, splitting plane and the space and content of
+/// its children.
+type Level<T, D> = (usize, Plane<T>, [(AABB<T, D>, ChildContent<T>); 2]);
+
+/// Split a node given its content, or return the shapes of its leaf if it isn't split.
+fn split_level<T: Float, D: Dim>(
+    config: &BuilderConfig<T>,
+    space: &AABB<T, D>,
+    candidates: &Candidates<T>,
+    shapes: &[usize],
+) -> Result<Level<T, D>, Vec<usize>> {
+    let nb_shapes = shapes.len();
+    let Some((_, split, planar_side)) = find_split(config, space, candidates, nb_shapes) else {
+        return Err(leaf_shapes(candidates, shapes));
+    };
+    let (l_space, r_space) = split_space(space, &split);
+    let (left, right) = split_node(config, candidates, shapes, &split, planar_side);
+    Ok((nb_shapes, split, [(l_space, left), (r_space, right)]))
+}
+
+/// Build the children of a node split at `depth` and return the nodes of the subtree.
+fn build_children<T: Float, D: Dim>(
+    config: &BuilderConfig<T>,
+    (nb_shapes, split, [(l_space, left), (r_space, right)]): Level<T, D>,
+    depth: usize,
+) -> Vec<LazyNode<T, D>> {
+    let ((left_candidates, left_shapes), (right_candidates, right_shapes)) = (left, right);
+    let (tree_left, tree_right) = config.join(
+        nb_shapes,
+        || build_levels(config, &l_space, left_candidates, left_shapes, depth + 1),
+        || build_levels(config, &r_space, right_candidates, right_shapes, depth + 1),
+    );
+
+    let mut tree = Vec::with_capacity(1 + tree_left.len() + tree_right.len());
+    let l_child = 1;
+    let r_child = 1 + tree_left.len();
+    tree.push(LazyNode::Node {
+        l_child,
+        l_space,
+        r_child,
+        r_space,
+        split,
+    });
+    tree.extend(tree_left.into_iter().map(|node| node.moved(l_child)));
+    tree.extend(tree_right.into_iter().map(|node| node.moved(r_child)));
+    tree
+}
+
+impl<T: Float, D: Dim> LazyNode<T, D> {
+    /// Move indices of the region by `offset`.
+    fn moved(mut self, offset: usize) -> Self {
+        if let LazyNode::Node {
+            l_child, r_child, ..
+        } = &mut self
+        {
+            *l_child += offset;
+            *r_child += offset;
+        }
+        self
+    }
+}
+
+/// Move the nodes of a lazy tree to a `KDTree` in depth-first order, building its
+/// regions. Return the nodes and the depth of the tree.
+fn flatten<T: Float, D: Dim>(
+    root: Vec<LazyNode<T, D>>,
+    config: &BuilderConfig<T>,
+) -> (Vec<KDTreeNode<T, D>>, usize) {
+    let mut regions = vec![root];
+    let mut tree = vec![];
+    let mut depth = 0;
+    // Node to move (region and index in the region), its depth and its parent in `tree`
+    // with the side of the node
+    let mut stack = vec![(0, 0, 1, None)];
+    while let Some((region, index, node_depth, parent)) = stack.pop() {
+        let node = mem::replace(
+            &mut regions[region][index],
+            LazyNode::Leaf { shapes: vec![] },
+        );
+        let node_index = tree.len();
+        let node = match node {
+            LazyNode::Deferred(deferred) => {
+                deferred.nodes(config);
+                regions.push(deferred.nodes.into_inner().unwrap());
+                stack.push((regions.len() - 1, 0, node_depth, parent));
+                continue;
+            }
+            LazyNode::Leaf { shapes } => {
+                depth = depth.max(node_depth);
+                KDTreeNode::Leaf { shapes }
+            }
+            LazyNode::Node {
+                l_child,
+                l_space,
+                r_child,
+                r_space,
+                split,
+            } => {
+                stack.push((
+                    region,
+                    r_child,
+                    node_depth + 1,
+                    Some((node_index, Side::Right)),
+                ));
+                stack.push((
+                    region,
+                    l_child,
+                    node_depth + 1,
+                    Some((node_index, Side::Left)),
+                ));
+                KDTreeNode::Node {
+                    l_child: 0,
+                    l_space,
+                    r_child: 0,
+                    r_space,
+                    split,
+                }
+            }
+        };
+        if let Some((parent, side)) = parent
+            && let KDTreeNode::Node {
+                l_child, r_child, ..
+            } = &mut tree[parent]
+        {
+            match side {
+                Side::Left => *l_child = node_index,
+                _ => *r_child = node_index,
+            }
+        }
+        tree.push(node);
+    }
+    (tree, depth)
+}
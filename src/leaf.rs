@@ -0,0 +1,12 @@
+/// Data stored in the leaves of a [KDTree](struct.KDTree.html).
+///
+/// Index trees (the default) store the indices of the shapes of each leaf in a `Vec<usize>`
+/// and are the only ones returning indices from their queries, see
+/// [KDTree::intersect](struct.KDTree.html#method.intersect). Other payloads are built from
+/// these indices once the tree is built, see
+/// [KDTree::build_with_leaves](struct.KDTree.html#method.build_with_leaves), and are handed
+/// to queries with [KDTree::intersect_leaves](struct.KDTree.html#method.intersect_leaves).
+/// They don't have to keep the indices.
+pub trait LeafData {}
+
+impl LeafData for Vec<usize> {}
@@ -10,18 +10,60 @@
 //!
 //! ```toml
 //! [dependencies]
-//! kdtree-ray="1.2.2"
+//! kdtree-ray="2.0.0"
 //! ```
 //!
 //! # Usage & Tips
 //!
 //! To create a [KD-tree](struct.KDtree.html) you only need to implement
 //! the [BoundingBox](trait.BoundingBox.html) on the object.
+//! Bounding boxes can also be given directly with [KDTree::from_bounds](struct.KDTree.html#method.from_bounds),
+//! and references, `Box`es and `Arc`s of bounded shapes are bounded too.
+//! A [Scene](struct.Scene.html) owns its shapes and returns them from queries, instead of
+//! indices into your own list of shapes.
+//! Shapes can be reordered to match the order of the leaves, so the shapes of a leaf are
+//! close in memory, see [KDTree::leaf_order](struct.KDTree.html#method.leaf_order).
+//! Leaves can also store your own data instead of indices, see [LeafData](trait.LeafData.html).
 //!
 //! If you're doing a raytracer each mesh could contain a KD-tree of triangles.
 //! Since `KDtree` his implementing `BoundingBox` itself you can create a KDtree
 //! of meshes in your scene.
 //!
+//! Every type is generic over the [Float](trait.Float.html) type of the coordinates, `f32`
+//! by default. Use `f64` (`KDTree<f64>`, `AABB<f64>`, ...) for scenes with large coordinates.
+//!
+//! Trees are 3D by default. For 2D shapes (sprites, 2D visibility, ...) implement
+//! `Bounded<f32, D2>` returning rectangles and build a [KDTree2](type.KDTree2.html) queried
+//! with 2D rays.
+//!
+//! Rays can be given with the vector types of `cgmath`, [glam](https://crates.io/crates/glam),
+//! [nalgebra](https://crates.io/crates/nalgebra) or [mint](https://crates.io/crates/mint)
+//! (enable the feature of the same name), see [ToVector](trait.ToVector.html).
+//!
+//! Trees are built in the global [rayon](https://crates.io/crates/rayon) thread pool by
+//! default. Use [BuilderConfig::with_threading](struct.BuilderConfig.html#method.with_threading)
+//! to build in your own pool or on the calling thread only (recommended when building many
+//! small trees). Disable the default `rayon` feature to drop the dependency.
+//! Long builds can report their progress and be cancelled, see
+//! [BuilderConfig::with_progress](struct.BuilderConfig.html#method.with_progress) and
+//! [KDTree::try_build_config](struct.KDTree.html#method.try_build_config). For interactive
+//! previews, [KDTree::build_with_deadline](struct.KDTree.html#method.build_with_deadline)
+//! returns a coarser tree when time runs out.
+//! A [LazyKDTree](struct.LazyKDTree.html) only builds the regions entered by queries.
+//!
+//! Trees can be saved with [KDTree::write_to](struct.KDTree.html#method.write_to) and loaded
+//! with [KDTree::read_from](struct.KDTree.html#method.read_from). For scenes larger than the
+//! memory, a [StreamingBuilder](struct.StreamingBuilder.html) builds the tree out-of-core from
+//! a stream of bounding boxes and writes it directly in this format.
+//!
+//! To place the same mesh many times in your scene with different transformations use an
+//! [InstanceTree](struct.InstanceTree.html) of [Instance](struct.Instance.html)s sharing
+//! the KD-tree of the mesh.
+//!
+//! For triangle meshes, a [TriangleMesh](struct.TriangleMesh.html) stores the triangles of
+//! each leaf precomputed in packets of 4 or 8 and returns the closest triangle hit by a ray
+//! instead of the candidates.
+//!
 //! # Example
 //!
 //! ```
@@ -59,17 +101,48 @@
 mod aabb;
 mod candidate;
 mod config;
+mod deadline;
+mod dim;
+mod edit;
+mod export;
+mod fingerprint;
+mod float;
+mod instance;
 mod kdnode;
 mod kdtree;
+mod lazy;
+mod leaf;
+mod motion;
 mod plane;
+mod progress;
 mod ray;
+mod refit;
+mod reorder;
+mod scene;
+mod serialize;
+mod stats;
+mod stream;
+mod triangle;
+mod validate;
+mod vector;
 
 pub use aabb::*;
-pub use config::BuilderConfig;
-pub use kdtree::KDTree;
-
-type Point3 = cgmath::Vector3<f32>;
-type Vector3 = cgmath::Vector3<f32>;
+pub use config::{BuilderConfig, Threading};
+pub use deadline::DeadlineReport;
+pub use dim::{D2, D3, Dim};
+pub use float::Float;
+pub use instance::{Instance, InstanceTree};
+pub use kdtree::{KDTree, KDTree2, KDTree3};
+pub use lazy::LazyKDTree;
+pub use leaf::LeafData;
+pub use motion::{MotionBounded, MotionKDTree};
+pub use progress::{BuildError, CancellationToken};
+pub use refit::RefitReport;
+pub use scene::Scene;
+pub use stats::{QueryStats, TreeStats};
+pub use stream::StreamingBuilder;
+pub use triangle::{Triangle, TriangleHit, TriangleLeaf, TriangleMesh};
+pub use validate::ValidationError;
+pub use vector::ToVector;
 
-#[macro_use]
-extern crate enum_map;
+type Vector3<T> = cgmath::Vector3<T>;
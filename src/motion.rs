@@ -0,0 +1,136 @@
+use crate::aabb::*;
+use crate::config::BuilderConfig;
+use crate::kdnode::KDTreeNode;
+use crate::kdtree::{KDTree, dedup_shapes};
+use crate::ray::Ray;
+use crate::{Float, ToVector};
+
+/// Your moving shapes needs to implement `MotionBounded` trait to build a
+/// [MotionKDTree](struct.MotionKDTree.html) around it.
+///
+/// The bounding box of the shape at a time `t` between `0.` and `1.` is expected to be
+/// inside the linear interpolation of the bounding boxes at `t = 0.` and `t = 1.`.
+pub trait MotionBounded<T: Float = f32> {
+    /// This function return the **Axis-aligned bounding boxes** (`AABB`) of the object
+    /// at `t = 0.`.
+    fn bound_start(&self) -> AABB<T>;
+
+    /// This function return the **Axis-aligned bounding boxes** (`AABB`) of the object
+    /// at `t = 1.`.
+    fn bound_end(&self) -> AABB<T>;
+}
+
+/// A KD-tree of moving shapes for motion blur.
+///
+/// Nodes store their bounding boxes at `t = 0.` and `t = 1.` and queries interpolate them
+/// at the time of the ray. Fast-moving shapes are then only visited by rays close to them
+/// in time instead of every ray crossing their whole motion.
+#[derive(Clone, Debug)]
+pub struct MotionKDTree<T: Float = f32> {
+    tree: KDTree<T>,
+    /// Bounding boxes of every node at `t = 0.` and `t = 1.`
+    bounds: Vec<[AABB<T>; 2]>,
+}
+
+impl<T: Float> MotionKDTree<T> {
+    /// This function is used to build a new motion KD-tree. You need to provide a
+    /// `Vec` of shapes that implement `MotionBounded` trait.
+    /// You also should give a configuration.
+    /// Panic if the `shapes` is empty.
+    pub fn build_config<S: MotionBounded<T>>(shapes: &[S], config: &BuilderConfig<T>) -> Self {
+        let keyframes: Vec<[AABB<T>; 2]> = shapes
+            .iter()
+            .map(|s| [s.bound_start(), s.bound_end()])
+            .collect();
+        // The tree is built around the boxes covering the whole motion of the shapes
+        let swept: Vec<AABB<T>> = keyframes
+            .iter()
+            .map(|[start, end]| {
+                let mut bb = start.clone();
+                bb.merge(end);
+                bb
+            })
+            .collect();
+        let tree = KDTree::build_config(&swept, config);
+
+        // Compute the bounds of the nodes bottom-up.
+        // Children are always stored after their parent in a freshly built tree.
+        let mut bounds = vec![[AABB::empty(), AABB::empty()]; tree.tree.len()];
+        for index in (0..tree.tree.len()).rev() {
+            let mut node_bounds = [AABB::empty(), AABB::empty()];
+            match &tree.tree[index] {
+                KDTreeNode::Leaf { shapes } => {
+                    for &shape in shapes {
+                        node_bounds[0].merge(&keyframes[shape][0]);
+                        node_bounds[1].merge(&keyframes[shape][1]);
+                    }
+                }
+                KDTreeNode::Node {
+                    l_child, r_child, ..
+                } => {
+                    for child in [l_child, r_child] {
+                        node_bounds[0].merge(&bounds[*child][0]);
+                        node_bounds[1].merge(&bounds[*child][1]);
+                    }
+                }
+            }
+            bounds[index] = node_bounds;
+        }
+
+        MotionKDTree { tree, bounds }
+    }
+
+    /// This function is used to build a new motion KD-tree. You need to provide a
+    /// `Vec` of shapes that implement `MotionBounded` trait.
+    /// Take a default configuration.
+    /// Panic if the `shapes` is empty.
+    pub fn build<S: MotionBounded<T>>(shapes: &[S]) -> Self {
+        Self::build_config(shapes, &BuilderConfig::default())
+    }
+
+    /// This function takes a ray at a given `time` (between `0.` and `1.`) and return a
+    /// reduced list of shapes that can be intersected by the ray.
+    pub fn intersect<V: ToVector<T>>(
+        &self,
+        ray_origin: &V,
+        ray_direction: &V,
+        time: T,
+    ) -> Vec<usize> {
+        let ray = Ray::new(ray_origin, ray_direction).with_time(time);
+        let mut result = vec![];
+        self.tree.visit_leaves_with(
+            &mut (),
+            |child, _| ray.intersect(&self.bound_at(child, ray.time)),
+            &mut |shapes: &Vec<usize>, _: &mut ()| result.extend(shapes),
+        );
+        dedup_shapes(&mut result);
+        result
+    }
+
+    /// Interpolate the bounding box of a node at a given time.
+    fn bound_at(&self, node: usize, time: T) -> AABB<T> {
+        let [start, end] = &self.bounds[node];
+        // Empty leaves have empty bounds that can't be interpolated
+        if start.is_empty() {
+            return AABB::empty();
+        }
+        start.lerp(end, time)
+    }
+}
+
+impl<T: Float> MotionBounded<T> for MotionKDTree<T> {
+    fn bound_start(&self) -> AABB<T> {
+        self.bounds[0][0].clone()
+    }
+
+    fn bound_end(&self) -> AABB<T> {
+        self.bounds[0][1].clone()
+    }
+}
+
+impl<T: Float> Bounded<T> for MotionKDTree<T> {
+    /// Bounding box covering the whole motion of the shapes.
+    fn bound(&self) -> AABB<T> {
+        self.tree.bound()
+    }
+}
@@ -1,59 +1,54 @@
-use enum_map::{Enum, EnumMap};
+use std::fmt;
 
-use crate::AABB;
+use crate::dim::Dim;
+use crate::{AABB, Float};
 
-/// 3D dimensions enum.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Enum)]
-pub enum Dimension {
-    X,
-    Y,
-    Z,
-}
+/// Axis of the space (`0` for X, `1` for Y, ...).
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Dimension(pub usize);
 
 impl Dimension {
-    pub fn get_map<T: Clone>(value: T) -> EnumMap<Dimension, T> {
-        enum_map! {
-            Dimension::X => value.clone(),
-            Dimension::Y => value.clone(),
-            Dimension::Z => value.clone()
+    /// First axis of the space.
+    pub const X: Dimension = Dimension(0);
+
+    /// Iterate over the axes of a space of dimension `D`.
+    pub fn all<D: Dim>() -> impl Iterator<Item = Dimension> {
+        (0..D::N).map(Dimension)
+    }
+}
+
+impl fmt::Debug for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            0 => write!(f, "X"),
+            1 => write!(f, "Y"),
+            2 => write!(f, "Z"),
+            axis => write!(f, "Dimension({axis})"),
         }
     }
 }
 
-/// 3D plane.
-#[derive(Clone, Debug)]
-pub struct Plane {
+/// Axis-aligned splitting plane (a line in 2D).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plane<T: Float> {
     pub dimension: Dimension,
-    pub pos: f32,
+    pub pos: T,
 }
 
-impl Plane {
+impl<T: Float> Plane<T> {
     /// Create a new plane.
-    pub fn new(dimension: Dimension, pos: f32) -> Self {
+    pub fn new(dimension: Dimension, pos: T) -> Self {
         Plane { dimension, pos }
     }
 
-    /// Create a new plane on the X axis.
-    pub fn new_x(pos: f32) -> Self {
-        Plane::new(Dimension::X, pos)
-    }
-
-    /// Create a new plane on the Y axis.
-    pub fn new_y(pos: f32) -> Self {
-        Plane::new(Dimension::Y, pos)
-    }
-
-    /// Create a new plane on the Z axis.
-    pub fn new_z(pos: f32) -> Self {
-        Plane::new(Dimension::Z, pos)
+    /// Check if the plane is cutting the given space.
+    pub fn is_cutting<D: Dim>(&self, space: &AABB<T, D>) -> bool {
+        let (min, max) = self.axis_bounds(space);
+        self.pos > min && self.pos < max
     }
 
-    /// Check if the plane is cutting the given space.
-    pub fn is_cutting(&self, space: &AABB) -> bool {
-        match self.dimension {
-            Dimension::X => self.pos > space.min.x && self.pos < space.max.x,
-            Dimension::Y => self.pos > space.min.y && self.pos < space.max.y,
-            Dimension::Z => self.pos > space.min.z && self.pos < space.max.z,
-        }
+    /// Retrieve the minimum and maximum of an AABB along the dimension of the plane.
+    pub fn axis_bounds<D: Dim>(&self, bb: &AABB<T, D>) -> (T, T) {
+        (bb.min[self.dimension.0], bb.max[self.dimension.0])
     }
 }
@@ -0,0 +1,155 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use crate::Float;
+use crate::config::BuilderConfig;
+
+/// Token used to cancel a build from another thread.
+///
+/// Clones share the same state: cancelling one of them cancels the builds using any of them,
+/// see [BuilderConfig::with_cancellation](struct.BuilderConfig.html#method.with_cancellation).
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a new token, not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the builds using this token. Running builds stop as soon as possible.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Check if the token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Error returned by a build that didn't finish.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// The build was cancelled with its [CancellationToken](struct.CancellationToken.html).
+    Cancelled,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Cancelled => write!(f, "the build was cancelled"),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+impl From<BuildError> for io::Error {
+    fn from(err: BuildError) -> Self {
+        io::Error::other(err)
+    }
+}
+
+/// Callback fed with the progress of a build (between `0.` and `1.`).
+#[derive(Clone)]
+pub(crate) struct ProgressCallback(pub Arc<dyn Fn(f32) + Send + Sync>);
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ProgressCallback")
+    }
+}
+
+/// Progress of a build, shared by the threads building it.
+///
+/// Each node gets a share of the work: `1.` for the root, split between the children
+/// proportionally to their number of shapes. The progress is the sum of the shares of the
+/// finished leaves, i.e. the fraction of the shapes placed in their final leaves.
+pub(crate) struct BuildProgress<'a> {
+    callback: Option<&'a ProgressCallback>,
+    cancellation: Option<&'a CancellationToken>,
+    /// Sum of the shares of the finished leaves (bits of a `f64`).
+    done: AtomicU64,
+    /// Last progress reported, in percent.
+    reported: AtomicU32,
+}
+
+impl<'a> BuildProgress<'a> {
+    /// Track the progress of a build with the callback and the token of `config`.
+    pub fn new<T: Float>(config: &'a BuilderConfig<T>) -> Self {
+        BuildProgress {
+            callback: config.progress.as_ref(),
+            cancellation: config.cancellation(),
+            done: AtomicU64::new(0f64.to_bits()),
+            reported: AtomicU32::new(0),
+        }
+    }
+
+    /// Progress of a build that can't be cancelled and doesn't report anything.
+    pub fn none() -> Self {
+        BuildProgress {
+            callback: None,
+            cancellation: None,
+            done: AtomicU64::new(0f64.to_bits()),
+            reported: AtomicU32::new(0),
+        }
+    }
+
+    /// Fail if the build was cancelled.
+    pub fn check(&self) -> Result<(), BuildError> {
+        match self.cancellation {
+            Some(token) if token.is_cancelled() => Err(BuildError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Record a finished leaf given the share of its node.
+    /// The callback is called each time the progress reaches a new percent.
+    pub fn finish_leaf(&self, share: f64) {
+        let previous = self
+            .done
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |done| {
+                Some((f64::from_bits(done) + share).to_bits())
+            })
+            .unwrap();
+        let Some(callback) = self.callback else {
+            return;
+        };
+        let done = (f64::from_bits(previous) + share).min(1.);
+        let percent = (done * 100.) as u32;
+        if self.reported.fetch_max(percent, Ordering::Relaxed) < percent {
+            (callback.0)(done as f32);
+        }
+    }
+
+    /// Sum of the shares of the finished leaves, between `0.` and `1.`.
+    pub fn done(&self) -> f32 {
+        f64::from_bits(self.done.load(Ordering::Relaxed)).min(1.) as f32
+    }
+
+    /// Report the end of the build.
+    pub fn finish(&self) {
+        if let Some(callback) = self.callback
+            && self.reported.swap(100, Ordering::Relaxed) < 100
+        {
+            (callback.0)(1.);
+        }
+    }
+}
+
+/// Share of the work of the children of a node given the share of the node and the number
+/// of shapes of each child.
+pub(crate) fn split_share(share: f64, nb_left: usize, nb_right: usize) -> (f64, f64) {
+    let total = (nb_left + nb_right) as f64;
+    if total == 0. {
+        return (share / 2., share / 2.);
+    }
+    (
+        share * nb_left as f64 / total,
+        share * nb_right as f64 / total,
+    )
+}
@@ -1,73 +1,86 @@
-use crate::{AABB, Point3, Vector3};
+use crate::dim::Dim;
+use crate::{AABB, Float, ToVector};
 
-/// A 3D ray
-pub struct Ray {
+/// A 2D or 3D ray
+pub struct Ray<T: Float, D: Dim> {
     /// The origin of the ray
-    origin: Vector3,
+    origin: D::Vector<T>,
     /// The inverse of the direction of the ray (1 / direction)
-    inv_direction: Vector3,
-    /// The sign of the direction of the ray (0 if negative, 1 if positive)
-    sign: [bool; 3],
+    inv_direction: D::Vector<T>,
+    /// The sign of the direction of the ray for each axis (bit set if negative)
+    sign: u8,
+    /// The time of the ray between `0.` and `1.` (used for motion blur)
+    pub time: T,
 }
 
-impl Ray {
-    pub fn new(origin: &Vector3, direction: &Vector3) -> Self {
-        let inv_direction = Vector3::new(1. / direction.x, 1. / direction.y, 1. / direction.z);
-        let sign = [direction.x < 0., direction.y < 0., direction.z < 0.];
+impl<T: Float, D: Dim> Ray<T, D> {
+    pub fn new<V: ToVector<T, D>>(origin: &V, direction: &V) -> Self {
+        let origin = origin.to_vector();
+        let direction = direction.to_vector();
+        let mut inv_direction = direction;
+        let mut sign = 0;
+        for axis in 0..D::N {
+            inv_direction[axis] = T::one() / direction[axis];
+            if direction[axis] < T::zero() {
+                sign |= 1 << axis;
+            }
+        }
 
         Self {
-            origin: *origin,
+            origin,
             inv_direction,
             sign,
+            time: T::zero(),
         }
     }
 
-    fn get_aabb_sign(aabb: &AABB, sign: bool) -> Point3 {
-        if sign { aabb.max } else { aabb.min }
+    pub fn with_time(mut self, time: T) -> Self {
+        self.time = time.max(T::zero()).min(T::one());
+        self
     }
 
-    pub fn intersect(&self, aabb: &AABB) -> bool {
-        let mut ray_min =
-            (Self::get_aabb_sign(aabb, self.sign[0]).x - self.origin.x) * self.inv_direction.x;
-        let mut ray_max =
-            (Self::get_aabb_sign(aabb, !self.sign[0]).x - self.origin.x) * self.inv_direction.x;
-
-        let y_min =
-            (Self::get_aabb_sign(aabb, self.sign[1]).y - self.origin.y) * self.inv_direction.y;
-        let y_max =
-            (Self::get_aabb_sign(aabb, !self.sign[1]).y - self.origin.y) * self.inv_direction.y;
+    /// Distances along the ray to the near and far borders of an AABB on an axis.
+    fn axis_range(&self, aabb: &AABB<T, D>, axis: usize) -> (T, T) {
+        let (near, far) = if self.sign & (1 << axis) != 0 {
+            (aabb.max[axis], aabb.min[axis])
+        } else {
+            (aabb.min[axis], aabb.max[axis])
+        };
+        (
+            (near - self.origin[axis]) * self.inv_direction[axis],
+            (far - self.origin[axis]) * self.inv_direction[axis],
+        )
+    }
 
-        if (ray_min > y_max) || (y_min > ray_max) {
-            return false;
-        }
+    pub fn intersect(&self, aabb: &AABB<T, D>) -> bool {
+        self.entry(aabb).is_some()
+    }
 
-        // Using the following solution significantly decreases the performance
-        // ray_min = ray_min.max(y_min);
-        if y_min > ray_min {
-            ray_min = y_min;
-        }
+    /// Distance along the ray to the point where it enters an AABB, `0.` if the origin is
+    /// inside it, or `None` if the ray misses it.
+    pub fn entry(&self, aabb: &AABB<T, D>) -> Option<T> {
+        let (mut ray_min, mut ray_max) = self.axis_range(aabb, 0);
 
-        // Using the following solution significantly decreases the performance
-        // ray_max = ray_max.min(y_max);
-        if y_max < ray_max {
-            ray_max = y_max;
-        }
+        for axis in 1..D::N {
+            let (axis_min, axis_max) = self.axis_range(aabb, axis);
 
-        let z_min =
-            (Self::get_aabb_sign(aabb, self.sign[2]).z - self.origin.z) * self.inv_direction.z;
-        let z_max =
-            (Self::get_aabb_sign(aabb, !self.sign[2]).z - self.origin.z) * self.inv_direction.z;
+            if (ray_min > axis_max) || (axis_min > ray_max) {
+                return None;
+            }
 
-        if (ray_min > z_max) || (z_min > ray_max) {
-            return false;
-        }
+            // Using the following solution significantly decreases the performance
+            // ray_min = ray_min.max(axis_min);
+            if axis_min > ray_min {
+                ray_min = axis_min;
+            }
 
-        // Using the following solution significantly decreases the performance
-        // ray_max = ray_max.min(y_max);
-        if z_max < ray_max {
-            ray_max = z_max;
+            // Using the following solution significantly decreases the performance
+            // ray_max = ray_max.min(axis_max);
+            if axis_max < ray_max {
+                ray_max = axis_max;
+            }
         }
 
-        ray_max > 0.0
+        (ray_max > T::zero()).then(|| ray_min.max(T::zero()))
     }
 }
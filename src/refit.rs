@@ -0,0 +1,134 @@
+use crate::Float;
+use crate::aabb::*;
+use crate::dim::Dim;
+use crate::kdnode::{KDTreeNode, split_space};
+use crate::kdtree::KDTree;
+
+/// A tree is considered degraded when its SAH cost grows by this factor.
+pub(crate) static DEGRADATION_THRESHOLD: f32 = 1.5;
+
+/// Report of a [KDTree::refit](struct.KDTree.html#method.refit).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RefitReport<T: Float = f32> {
+    /// Number of times a shape lies in the region of a leaf that doesn't reference it.
+    /// If it's not `0` queries can miss shapes and the tree must be rebuilt.
+    pub nb_missing_references: usize,
+    /// Number of references to a shape that doesn't lie in the region of its leaf anymore.
+    /// Stale references don't produce wrong results but slow down queries.
+    pub nb_stale_references: usize,
+    /// SAH cost of the tree before the refit.
+    pub sah_cost_before: T,
+    /// SAH cost of the tree after the refit.
+    pub sah_cost_after: T,
+}
+
+impl<T: Float> RefitReport<T> {
+    /// Check if queries on the refitted tree still return every shape a ray can hit.
+    pub fn is_valid(&self) -> bool {
+        self.nb_missing_references == 0
+    }
+
+    /// Check if the tree should be rebuilt, either because it's invalid or because its SAH
+    /// cost increased too much.
+    pub fn rebuild_recommended(&self) -> bool {
+        !self.is_valid()
+            || self.sah_cost_after > self.sah_cost_before * T::cast(DEGRADATION_THRESHOLD)
+    }
+}
+
+impl<T: Float, D: Dim> KDTree<T, D> {
+    /// Update the tree after shapes moved without changing its topology.
+    ///
+    /// `shapes` must be the same list of shapes (same order) the tree was built with, shapes
+    /// removed from the tree are ignored. Splitting planes are kept and the spaces of the
+    /// nodes are recomputed bottom-up to fit the new bounding boxes of the shapes.
+    ///
+    /// Since splitting planes are spatial a shape can leave the space of its leaves, making
+    /// the tree invalid. Check the returned report to know when a rebuild is needed.
+    ///
+    /// Panic if `shapes` doesn't have as many shapes as the tree was built with.
+    pub fn refit<S: Bounded<T, D>>(&mut self, shapes: &[S]) -> RefitReport<T> {
+        assert_eq!(
+            shapes.len(),
+            self.bounds.len(),
+            "refit needs the shapes the tree was built with"
+        );
+        let mut report = RefitReport {
+            nb_missing_references: 0,
+            nb_stale_references: 0,
+            sah_cost_before: self.sah_cost(),
+            sah_cost_after: T::zero(),
+        };
+
+        let bounds: Vec<AABB<T, D>> = shapes
+            .iter()
+            .enumerate()
+            .map(|(index, s)| {
+                if self.contains_shape(index) {
+                    s.bound()
+                } else {
+                    AABB::empty()
+                }
+            })
+            .collect();
+        let root_space = AABB::enclosing(&bounds);
+
+        // Compute the spaces delimited by the splitting planes top-down
+        let mut regions = vec![AABB::empty(); self.tree.len()];
+        let mut order = Vec::with_capacity(self.tree.len());
+        regions[0] = root_space.clone();
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            order.push(index);
+            if let KDTreeNode::Node {
+                l_child,
+                r_child,
+                split,
+                ..
+            } = &self.tree[index]
+            {
+                let (l_region, r_region) = split_space(&regions[index], split);
+                regions[*l_child] = l_region;
+                regions[*r_child] = r_region;
+                stack.push(*r_child);
+                stack.push(*l_child);
+            }
+        }
+
+        // Fit the spaces of the nodes to their shapes bottom-up
+        let mut spaces = vec![AABB::empty(); self.tree.len()];
+        for &index in order.iter().rev() {
+            match &mut self.tree[index] {
+                KDTreeNode::Leaf { shapes } => {
+                    for shape in shapes.iter() {
+                        let clipped = bounds[*shape].intersection(&regions[index]);
+                        if !clipped.is_empty() {
+                            spaces[index].merge(&clipped);
+                        }
+                    }
+                }
+                KDTreeNode::Node {
+                    l_child,
+                    l_space,
+                    r_child,
+                    r_space,
+                    ..
+                } => {
+                    *l_space = spaces[*l_child].clone();
+                    *r_space = spaces[*r_child].clone();
+                    spaces[index] = l_space.clone();
+                    spaces[index].merge(r_space);
+                }
+            }
+        }
+        self.space = root_space;
+        self.bounds = bounds;
+
+        // Check that shapes are still referenced by every leaf they lie in
+        (report.nb_missing_references, report.nb_stale_references) = self.count_references();
+        self.stale_references = report.nb_stale_references > 0;
+
+        report.sah_cost_after = self.sah_cost();
+        report
+    }
+}
@@ -0,0 +1,85 @@
+use std::mem;
+
+use crate::Float;
+use crate::dim::Dim;
+use crate::kdnode::KDTreeNode;
+use crate::kdtree::KDTree;
+
+impl<T: Float, D: Dim> KDTree<T, D> {
+    /// Compute an order of the shapes grouping them by leaf: shapes are sorted by the first
+    /// leaf referencing them in a depth-first traversal, left child first. Shapes not in the
+    /// tree come last.
+    ///
+    /// `order[i]` is the current index of the shape to put at index `i`. Reorder your
+    /// shapes with it and apply it to the tree with [reorder](#method.reorder), so the
+    /// shapes of a leaf are close in memory.
+    pub fn leaf_order(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.bounds.len());
+        let mut visited = vec![false; self.bounds.len()];
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            match &self.tree[node] {
+                KDTreeNode::Leaf { shapes } => {
+                    for &shape in shapes {
+                        if !mem::replace(&mut visited[shape], true) {
+                            order.push(shape);
+                        }
+                    }
+                }
+                KDTreeNode::Node {
+                    l_child, r_child, ..
+                } => {
+                    stack.push(*r_child);
+                    stack.push(*l_child);
+                }
+            }
+        }
+        order.extend((0..self.bounds.len()).filter(|&shape| !visited[shape]));
+        order
+    }
+
+    /// Renumber the shapes of the tree: the shape at index `order[i]` gets the index `i`.
+    /// See [leaf_order](#method.leaf_order).
+    ///
+    /// ### Panics
+    ///
+    /// * If `order` isn't a permutation of the indices of the shapes.
+    pub fn reorder(&mut self, order: &[usize]) {
+        assert_eq!(order.len(), self.bounds.len(), "order isn't a permutation");
+        let mut new_indices = vec![usize::MAX; order.len()];
+        for (new_index, &old_index) in order.iter().enumerate() {
+            assert!(
+                old_index < order.len() && new_indices[old_index] == usize::MAX,
+                "order isn't a permutation"
+            );
+            new_indices[old_index] = new_index;
+        }
+
+        for node in self.tree.iter_mut() {
+            if let KDTreeNode::Leaf { shapes } = node {
+                shapes
+                    .iter_mut()
+                    .for_each(|shape| *shape = new_indices[*shape]);
+            }
+        }
+        permute(&mut self.bounds, order);
+    }
+}
+
+/// Move the item at index `order[i]` to index `i`, `order` being a permutation.
+pub(crate) fn permute<S>(items: &mut [S], order: &[usize]) {
+    let mut done = vec![false; items.len()];
+    for start in 0..items.len() {
+        // Follow the cycle of the permutation starting at `start`
+        let mut i = start;
+        while !done[i] {
+            done[i] = true;
+            let j = order[i];
+            if j == start {
+                break;
+            }
+            items.swap(i, j);
+            i = j;
+        }
+    }
+}
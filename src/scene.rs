@@ -0,0 +1,143 @@
+use crate::aabb::*;
+use crate::config::BuilderConfig;
+use crate::dim::{D3, Dim};
+use crate::kdtree::KDTree;
+use crate::reorder::permute;
+use crate::stats::QueryStats;
+use crate::{Float, ToVector};
+
+/// KD-tree owning its shapes.
+///
+/// Queries return the shapes with their index instead of indices into a separate list
+/// that must be kept in sync with the tree. The index-based [KDTree](struct.KDTree.html)
+/// is still available with [tree](#method.tree).
+///
+/// ```
+/// use cgmath::Vector3;
+/// use kdtree_ray::{AABB, Scene};
+///
+/// let boxes: Vec<AABB> = (0..10)
+///     .map(|i| {
+///         let min = Vector3::new(i as f32, 0., 0.);
+///         AABB::new(min, min + Vector3::new(0.5, 1., 1.))
+///     })
+///     .collect();
+/// let scene = Scene::build(boxes);
+/// let origin = Vector3::new(2.2, -1., 0.5);
+/// let direction = Vector3::new(0., 1., 0.);
+/// let candidates = scene.intersect(&origin, &direction);
+/// assert!(candidates.contains(&(2, &scene.shapes()[2])));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Scene<S, T: Float = f32, D: Dim = D3> {
+    tree: KDTree<T, D>,
+    shapes: Vec<S>,
+}
+
+impl<S: Bounded<T, D>, T: Float, D: Dim> Scene<S, T, D> {
+    /// Build the KD-tree of the shapes given a configuration and keep the shapes.
+    /// Panic if the `shapes` is empty.
+    pub fn build_config(shapes: Vec<S>, config: &BuilderConfig<T>) -> Self {
+        let tree = KDTree::build_config(&shapes, config);
+        Scene { tree, shapes }
+    }
+
+    /// Build the KD-tree of the shapes with a default configuration and keep the shapes.
+    /// Panic if the `shapes` is empty.
+    pub fn build(shapes: Vec<S>) -> Self {
+        Self::build_config(shapes, &BuilderConfig::default())
+    }
+
+    /// Return the shapes that can be intersected by a ray, with their index.
+    pub fn intersect<V: ToVector<T, D>>(
+        &self,
+        ray_origin: &V,
+        ray_direction: &V,
+    ) -> Vec<(usize, &S)> {
+        let result = self.tree.intersect(ray_origin, ray_direction);
+        self.with_shapes(result)
+    }
+
+    /// Same as [intersect](#method.intersect) but also accumulates traversal counters
+    /// into `stats`.
+    pub fn intersect_with_stats<V: ToVector<T, D>>(
+        &self,
+        ray_origin: &V,
+        ray_direction: &V,
+        stats: &mut QueryStats,
+    ) -> Vec<(usize, &S)> {
+        let result = self
+            .tree
+            .intersect_with_stats(ray_origin, ray_direction, stats);
+        self.with_shapes(result)
+    }
+
+    /// Add a shape to the scene and return its index, see
+    /// [KDTree::insert](struct.KDTree.html#method.insert).
+    pub fn push(&mut self, shape: S) -> usize {
+        let index = self.shapes.len();
+        self.tree.insert(index, &shape.bound());
+        self.shapes.push(shape);
+        index
+    }
+
+    /// Reorder the shapes so the shapes of a leaf are close in memory, see
+    /// [KDTree::leaf_order](struct.KDTree.html#method.leaf_order). Return the order to apply
+    /// to other buffers indexed like the shapes: `order[i]` is the previous index of the
+    /// shape now at index `i`.
+    ///
+    /// ```
+    /// use cgmath::Vector3;
+    /// use kdtree_ray::{AABB, Scene};
+    ///
+    /// let boxes: Vec<AABB> = (0..1000)
+    ///     .map(|i| {
+    ///         let min = Vector3::new((i * 7 % 1000) as f32, 0., 0.);
+    ///         AABB::new(min, min + Vector3::new(0.5, 1., 1.))
+    ///     })
+    ///     .collect();
+    /// let mut scene = Scene::build(boxes.clone());
+    /// let order = scene.reorder_by_leaves();
+    /// for (index, bb) in scene.shapes().iter().enumerate() {
+    ///     assert_eq!(bb, &boxes[order[index]]);
+    /// }
+    /// let origin = Vector3::new(42.2, -1., 0.5);
+    /// let direction = Vector3::new(0., 1., 0.);
+    /// let candidates = scene.intersect(&origin, &direction);
+    /// assert!(candidates.iter().any(|(_, bb)| bb.min.x == 42.));
+    /// ```
+    pub fn reorder_by_leaves(&mut self) -> Vec<usize> {
+        let order = self.tree.leaf_order();
+        self.tree.reorder(&order);
+        permute(&mut self.shapes, &order);
+        order
+    }
+
+    /// Retrieve the KD-tree of the shapes.
+    pub fn tree(&self) -> &KDTree<T, D> {
+        &self.tree
+    }
+
+    /// Retrieve the shapes, in the order of their index.
+    pub fn shapes(&self) -> &[S] {
+        &self.shapes
+    }
+
+    /// Drop the KD-tree and return the shapes.
+    pub fn into_shapes(self) -> Vec<S> {
+        self.shapes
+    }
+
+    fn with_shapes(&self, indices: Vec<usize>) -> Vec<(usize, &S)> {
+        indices
+            .into_iter()
+            .map(|index| (index, &self.shapes[index]))
+            .collect()
+    }
+}
+
+impl<S, T: Float, D: Dim> Bounded<T, D> for Scene<S, T, D> {
+    fn bound(&self) -> AABB<T, D> {
+        self.tree.bound()
+    }
+}
@@ -0,0 +1,316 @@
+use std::io::{self, Read, Write};
+use std::mem;
+
+use crate::Float;
+use crate::aabb::*;
+use crate::config::BuilderConfig;
+use crate::dim::Dim;
+use crate::edit::relayout;
+use crate::kdnode::KDTreeNode;
+use crate::kdtree::KDTree;
+use crate::plane::{Dimension, Plane};
+
+static MAGIC: &[u8; 4] = b"KDTR";
+static VERSION: u32 = 1;
+
+const TAG_LEAF: u8 = 0;
+const TAG_NODE: u8 = 1;
+const TAG_BOUNDS: u8 = 2;
+const TAG_FOOTER: u8 = 3;
+
+impl<T: Float, D: Dim> KDTree<T, D> {
+    /// Write the tree in a compact binary format, read back with
+    /// [read_from](#method.read_from).
+    ///
+    /// The format is little-endian and starts with a header made of the magic bytes `KDTR`,
+    /// the version of the format, the size in bytes of the floats (4 or 8) and the number of
+    /// axes (`u32` each). It's followed by records starting with a tag byte:
+    /// * `0`: a leaf, its number of shapes (`u64`) and their indices (`u64` each).
+    /// * `1`: a node, its splitting axis (`u32`) and position, the index of its left child
+    ///   (`u64`) and its space, the index of its right child and its space.
+    /// * `2`: the bounding boxes of the shapes, their number (`u64`) and the boxes.
+    /// * `3`: the footer ending the file, the index of the root node (`u64`) and the space
+    ///   of the tree.
+    ///
+    /// Boxes are written as their minimum and then maximum coordinates. Nodes are numbered
+    /// in the order of their records but can be written in any order, which lets the
+    /// [StreamingBuilder](struct.StreamingBuilder.html) write them as soon as they're built.
+    ///
+    /// **Note**: The builder configuration isn't written.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut tree_writer = TreeWriter::new::<T, D>(writer)?;
+        for node in &self.tree {
+            tree_writer.write_node(node)?;
+        }
+        tree_writer.write_bounds(self.bounds.len(), self.bounds.iter().cloned().map(Ok))?;
+        tree_writer.finish(0, &self.space)
+    }
+
+    /// Read a tree written by [write_to](#method.write_to) or by a
+    /// [StreamingBuilder](struct.StreamingBuilder.html).
+    ///
+    /// The tree gets the default builder configuration, it's used by later edits.
+    ///
+    /// Fail with `InvalidData` if the data isn't a tree with the float type and the
+    /// dimension of `Self`. When the nodes don't form a tree (indices out of range, nodes
+    /// shared or forming cycles) the error wraps the
+    /// [ValidationError](enum.ValidationError.html), with the indices of the nodes in the
+    /// order of their records.
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a KD-tree"));
+        }
+        if read_u32(reader)? != VERSION {
+            return Err(invalid_data("unsupported version"));
+        }
+        if read_u32(reader)? as usize != mem::size_of::<T>() {
+            return Err(invalid_data("wrong float type"));
+        }
+        if read_u32(reader)? as usize != D::N {
+            return Err(invalid_data("wrong dimension"));
+        }
+
+        let mut nodes = vec![];
+        let mut bounds = None;
+        let (root, space) = loop {
+            match read_u8(reader)? {
+                TAG_LEAF => {
+                    let nb_shapes = read_u64(reader)?;
+                    let shapes: Vec<usize> = (0..nb_shapes)
+                        .map(|_| read_index(reader))
+                        .collect::<io::Result<_>>()?;
+                    nodes.push(KDTreeNode::Leaf { shapes });
+                }
+                TAG_NODE => {
+                    let dimension = read_u32(reader)? as usize;
+                    if dimension >= D::N {
+                        return Err(invalid_data("wrong splitting axis"));
+                    }
+                    let pos = read_float(reader)?;
+                    nodes.push(KDTreeNode::Node {
+                        l_child: read_index(reader)?,
+                        l_space: read_aabb(reader)?,
+                        r_child: read_index(reader)?,
+                        r_space: read_aabb(reader)?,
+                        split: Plane::new(Dimension(dimension), pos),
+                    });
+                }
+                TAG_BOUNDS => {
+                    if bounds.is_some() {
+                        return Err(invalid_data("duplicated bounds"));
+                    }
+                    let nb_shapes = read_u64(reader)?;
+                    bounds = Some(
+                        (0..nb_shapes)
+                            .map(|_| read_aabb(reader))
+                            .collect::<io::Result<Vec<_>>>()?,
+                    );
+                }
+                TAG_FOOTER => break (read_index(reader)?, read_aabb(reader)?),
+                _ => return Err(invalid_data("unknown record")),
+            }
+        };
+
+        let bounds = bounds.unwrap_or_default();
+
+        let mut kdtree = KDTree {
+            tree: nodes,
+            space,
+            depth: 0,
+            config: BuilderConfig::default(),
+            bounds,
+            reference_cost: T::zero(),
+            stale_references: false,
+        };
+        // Check that the nodes form a tree before following them
+        if root >= kdtree.tree.len() && !kdtree.tree.is_empty() {
+            return Err(invalid_data("node index out of range"));
+        }
+        kdtree
+            .check_nodes(root, kdtree.bounds.len())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        // Put the nodes in the order of a built tree, the root first
+        let mut nodes = mem::take(&mut kdtree.tree);
+        kdtree.tree.reserve_exact(nodes.len());
+        kdtree.depth = relayout(&mut nodes, root, &mut kdtree.tree);
+        kdtree.reference_cost = kdtree.sah_cost();
+        // The tree can have been refitted before being written
+        kdtree.stale_references = kdtree.count_references().1 > 0;
+        Ok(kdtree)
+    }
+}
+
+/// Writer of the records of a tree, see [KDTree::write_to].
+pub(crate) struct TreeWriter<'a, W: Write> {
+    writer: &'a mut W,
+    /// Number of nodes written so far.
+    nb_nodes: usize,
+}
+
+impl<'a, W: Write> TreeWriter<'a, W> {
+    /// Write the header of a tree.
+    pub fn new<T: Float, D: Dim>(writer: &'a mut W) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        write_u32(writer, VERSION)?;
+        write_u32(writer, mem::size_of::<T>() as u32)?;
+        write_u32(writer, D::N as u32)?;
+        Ok(TreeWriter {
+            writer,
+            nb_nodes: 0,
+        })
+    }
+
+    /// Write a node and return its index.
+    pub fn write_node<T: Float, D: Dim>(&mut self, node: &KDTreeNode<T, D>) -> io::Result<usize> {
+        let writer = &mut *self.writer;
+        match node {
+            KDTreeNode::Leaf { shapes } => {
+                write_u8(writer, TAG_LEAF)?;
+                write_u64(writer, shapes.len() as u64)?;
+                for &shape in shapes {
+                    write_u64(writer, shape as u64)?;
+                }
+            }
+            KDTreeNode::Node {
+                l_child,
+                l_space,
+                r_child,
+                r_space,
+                split,
+            } => {
+                write_u8(writer, TAG_NODE)?;
+                write_u32(writer, split.dimension.0 as u32)?;
+                write_float(writer, split.pos)?;
+                write_u64(writer, *l_child as u64)?;
+                write_aabb(writer, l_space)?;
+                write_u64(writer, *r_child as u64)?;
+                write_aabb(writer, r_space)?;
+            }
+        }
+        self.nb_nodes += 1;
+        Ok(self.nb_nodes - 1)
+    }
+
+    /// Write the nodes of a subtree (its root first) and return the index of its root.
+    pub fn write_subtree<T: Float, D: Dim>(
+        &mut self,
+        tree: Vec<KDTreeNode<T, D>>,
+    ) -> io::Result<usize> {
+        let root = self.nb_nodes;
+        for mut node in tree {
+            node.move_indices(root);
+            self.write_node(&node)?;
+        }
+        Ok(root)
+    }
+
+    /// Write the bounding boxes of the shapes given their number.
+    pub fn write_bounds<T: Float, D: Dim>(
+        &mut self,
+        nb_shapes: usize,
+        bounds: impl Iterator<Item = io::Result<AABB<T, D>>>,
+    ) -> io::Result<()> {
+        write_u8(self.writer, TAG_BOUNDS)?;
+        write_u64(self.writer, nb_shapes as u64)?;
+        for bb in bounds {
+            write_aabb(self.writer, &bb?)?;
+        }
+        Ok(())
+    }
+
+    /// Write the footer given the index of the root node and the space of the tree.
+    pub fn finish<T: Float, D: Dim>(self, root: usize, space: &AABB<T, D>) -> io::Result<()> {
+        write_u8(self.writer, TAG_FOOTER)?;
+        write_u64(self.writer, root as u64)?;
+        write_aabb(self.writer, space)?;
+        self.writer.flush()
+    }
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(crate) fn write_u8<W: Write>(writer: &mut W, value: u8) -> io::Result<()> {
+    writer.write_all(&[value])
+}
+
+pub(crate) fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+/// Write a float with the size of `T`.
+pub(crate) fn write_float<W: Write, T: Float>(writer: &mut W, value: T) -> io::Result<()> {
+    if mem::size_of::<T>() == 4 {
+        writer.write_all(&value.to_f32().unwrap().to_le_bytes())
+    } else {
+        writer.write_all(&value.to_f64().unwrap().to_le_bytes())
+    }
+}
+
+pub(crate) fn write_aabb<W: Write, T: Float, D: Dim>(
+    writer: &mut W,
+    aabb: &AABB<T, D>,
+) -> io::Result<()> {
+    for axis in 0..D::N {
+        write_float(writer, aabb.min[axis])?;
+    }
+    for axis in 0..D::N {
+        write_float(writer, aabb.max[axis])?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+pub(crate) fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Read an index, failing if it doesn't fit in a `usize`.
+fn read_index<R: Read>(reader: &mut R) -> io::Result<usize> {
+    usize::try_from(read_u64(reader)?).map_err(|_| invalid_data("index out of range"))
+}
+
+/// Read a float with the size of `T`.
+pub(crate) fn read_float<R: Read, T: Float>(reader: &mut R) -> io::Result<T> {
+    if mem::size_of::<T>() == 4 {
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
+        Ok(T::cast(f32::from_le_bytes(bytes)))
+    } else {
+        let mut bytes = [0; 8];
+        reader.read_exact(&mut bytes)?;
+        Ok(T::cast(f64::from_le_bytes(bytes)))
+    }
+}
+
+pub(crate) fn read_aabb<R: Read, T: Float, D: Dim>(reader: &mut R) -> io::Result<AABB<T, D>> {
+    let mut aabb = AABB::<T, D>::empty();
+    for axis in 0..D::N {
+        aabb.min[axis] = read_float(reader)?;
+    }
+    for axis in 0..D::N {
+        aabb.max[axis] = read_float(reader)?;
+    }
+    Ok(aabb)
+}
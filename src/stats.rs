@@ -0,0 +1,181 @@
+use std::collections::HashSet;
+use std::mem::size_of;
+
+use crate::Float;
+use crate::aabb::*;
+use crate::dim::Dim;
+use crate::kdnode::KDTreeNode;
+use crate::kdtree::KDTree;
+
+/// Statistics about the shape of a built KD-tree.
+///
+/// Useful to compare builds made with different [BuilderConfig](struct.BuilderConfig.html)
+/// or to catch quality regressions.
+#[derive(Clone, Debug, Default)]
+pub struct TreeStats<T: Float = f32> {
+    /// Total number of nodes (inner nodes and leaves).
+    pub nb_nodes: usize,
+    /// Number of leaves.
+    pub nb_leaves: usize,
+    /// Number of leaves that don't reference any shape.
+    pub nb_empty_leaves: usize,
+    /// Depth of the deepest leaf (the root is at depth `0`).
+    pub max_depth: usize,
+    /// Average depth of the leaves.
+    pub avg_depth: f32,
+    /// Number of leaves for each leaf size: `leaf_size_histogram[n]` is the number of
+    /// leaves referencing exactly `n` shapes.
+    pub leaf_size_histogram: Vec<usize>,
+    /// Total number of shape references stored in the leaves.
+    pub nb_references: usize,
+    /// Number of distinct shapes referenced by the leaves.
+    pub nb_unique_shapes: usize,
+    /// Average number of leaves referencing a shape (`nb_references / nb_unique_shapes`).
+    pub duplication_factor: f32,
+    /// Approximate memory used by the tree in bytes.
+    pub memory: usize,
+    /// Surface Area Heuristic cost of the whole tree (see
+    /// [KDTree::sah_cost](struct.KDTree.html#method.sah_cost)).
+    pub sah_cost: T,
+}
+
+/// Counters of the work done by queries.
+///
+/// Counters are accumulated over every query it's given to, use
+/// `QueryStats::default()` to start from zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueryStats {
+    /// Number of nodes visited (inner nodes and leaves).
+    pub nb_nodes_visited: usize,
+    /// Number of ray-AABB intersection tests performed.
+    pub nb_aabb_tests: usize,
+    /// Number of leaves visited.
+    pub nb_leaves_visited: usize,
+    /// Number of shape references emitted by the visited leaves (before deduplication).
+    pub nb_references: usize,
+}
+
+/// Receive traversal events of a query.
+/// Implemented by `()` to count nothing without any runtime cost.
+pub(crate) trait QueryCounter {
+    fn visit_node(&mut self) {}
+    fn test_aabb(&mut self) {}
+    fn visit_leaf(&mut self, _nb_shapes: usize) {}
+}
+
+impl QueryCounter for () {}
+
+impl QueryCounter for QueryStats {
+    fn visit_node(&mut self) {
+        self.nb_nodes_visited += 1;
+    }
+
+    fn test_aabb(&mut self) {
+        self.nb_aabb_tests += 1;
+    }
+
+    fn visit_leaf(&mut self, nb_shapes: usize) {
+        self.nb_leaves_visited += 1;
+        self.nb_references += nb_shapes;
+    }
+}
+
+impl<T: Float, D: Dim> KDTree<T, D> {
+    /// Compute statistics about the shape of the tree.
+    pub fn stats(&self) -> TreeStats<T> {
+        let mut stats = TreeStats {
+            nb_nodes: 0,
+            nb_leaves: 0,
+            nb_empty_leaves: 0,
+            max_depth: 0,
+            avg_depth: 0.,
+            leaf_size_histogram: vec![],
+            nb_references: 0,
+            nb_unique_shapes: 0,
+            duplication_factor: 0.,
+            memory: 0,
+            sah_cost: T::zero(),
+        };
+        let mut unique_shapes = HashSet::new();
+        let mut sum_depth = 0;
+
+        stats.memory = size_of::<Self>()
+            + self.tree.capacity() * size_of::<KDTreeNode<T, D>>()
+            + self.bounds.capacity() * size_of::<AABB<T, D>>();
+
+        let mut stack = vec![(0, 0)];
+        while let Some((index, depth)) = stack.pop() {
+            stats.nb_nodes += 1;
+            match &self.tree[index] {
+                KDTreeNode::Leaf { shapes } => {
+                    stats.nb_leaves += 1;
+                    stats.max_depth = stats.max_depth.max(depth);
+                    sum_depth += depth;
+                    if shapes.is_empty() {
+                        stats.nb_empty_leaves += 1;
+                    }
+                    if stats.leaf_size_histogram.len() <= shapes.len() {
+                        stats.leaf_size_histogram.resize(shapes.len() + 1, 0);
+                    }
+                    stats.leaf_size_histogram[shapes.len()] += 1;
+                    stats.nb_references += shapes.len();
+                    unique_shapes.extend(shapes.iter().copied());
+                    stats.memory += shapes.capacity() * size_of::<usize>();
+                }
+                KDTreeNode::Node {
+                    l_child, r_child, ..
+                } => {
+                    stack.push((*r_child, depth + 1));
+                    stack.push((*l_child, depth + 1));
+                }
+            }
+        }
+
+        stats.avg_depth = sum_depth as f32 / stats.nb_leaves as f32;
+        stats.nb_unique_shapes = unique_shapes.len();
+        if stats.nb_unique_shapes > 0 {
+            stats.duplication_factor = stats.nb_references as f32 / stats.nb_unique_shapes as f32;
+        }
+        stats.sah_cost = self.sah_cost();
+        stats
+    }
+
+    /// Compute the Surface Area Heuristic cost of the whole tree with the costs of the
+    /// configuration used to build it.
+    pub fn sah_cost(&self) -> T {
+        let root_surface = self.space.surface();
+        let surface_ratio = |space: &AABB<T, D>| {
+            if space.is_empty() {
+                T::zero()
+            } else if root_surface > T::zero() {
+                space.surface() / root_surface
+            } else {
+                T::one()
+            }
+        };
+
+        let mut cost = T::zero();
+        let mut stack = vec![(0, &self.space)];
+        while let Some((index, space)) = stack.pop() {
+            match &self.tree[index] {
+                KDTreeNode::Leaf { shapes } => {
+                    cost += self.config.cost_intersection()
+                        * T::cast(shapes.len())
+                        * surface_ratio(space);
+                }
+                KDTreeNode::Node {
+                    l_child,
+                    l_space,
+                    r_child,
+                    r_space,
+                    ..
+                } => {
+                    cost += self.config.cost_traversal() * surface_ratio(space);
+                    stack.push((*r_child, r_space));
+                    stack.push((*l_child, l_space));
+                }
+            }
+        }
+        cost
+    }
+}
@@ -0,0 +1,597 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::iter;
+use std::marker::PhantomData;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+use crate::Float;
+use crate::aabb::*;
+use crate::candidate::{Candidate, Candidates, EventKind, Side, events};
+use crate::config::BuilderConfig;
+use crate::dim::{D3, Dim};
+use crate::kdnode::{KDTreeNode, SplitSearch, build_tree, classify_item, leaf_cost, split_space};
+use crate::plane::{Dimension, Plane};
+use crate::progress::{BuildProgress, split_share};
+use crate::serialize::*;
+
+static DEFAULT_MEMORY_BUDGET: usize = 1 << 30;
+
+/// Counter used to give unique names to temporary files.
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Builder of KD-trees for scenes too large to fit in memory.
+///
+/// The bounding boxes of the shapes are streamed from an iterator or a reader. Their split
+/// candidates are sorted by chunks fitting in the memory budget, spilled to temporary files
+/// and merged. Nodes with more candidates than the budget allows are split by sweeping these
+/// files, smaller ones are built in memory. Nodes are written as soon as they're built in the
+/// format of [KDTree::write_to](struct.KDTree.html#method.write_to), so the tree is never
+/// fully in memory either.
+///
+/// The tree read back with [KDTree::read_from](struct.KDTree.html#method.read_from) is the
+/// same as the one built in memory with the same configuration.
+///
+/// **Note**: Besides the budget, the builder keeps one byte per shape in memory.
+///
+/// ```
+/// use kdtree_ray::{AABB, BuilderConfig, KDTree, StreamingBuilder};
+///
+/// let bounds = (0..100).map(|i| {
+///     let min = cgmath::Vector3::new(i as f32, 0., 0.);
+///     AABB::<f32>::new(min, min + cgmath::Vector3::new(1., 1., 1.))
+/// });
+/// let mut file = vec![];
+/// StreamingBuilder::new(BuilderConfig::default())
+///     .with_memory_budget(1 << 20)
+///     .build(bounds, &mut file)
+///     .unwrap();
+/// let tree: KDTree = KDTree::read_from(&mut file.as_slice()).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct StreamingBuilder<T: Float = f32, D: Dim = D3> {
+    config: BuilderConfig<T>,
+    /// Approximate number of bytes used for the candidates kept in memory.
+    memory_budget: usize,
+    /// Directory of the temporary files.
+    temp_dir: PathBuf,
+    dim: PhantomData<D>,
+}
+
+impl<T: Float, D: Dim> StreamingBuilder<T, D> {
+    /// Create a new builder given the configuration of the tree, with a memory budget of
+    /// 1 GiB and temporary files in the temporary directory of the system.
+    pub fn new(config: BuilderConfig<T>) -> Self {
+        StreamingBuilder {
+            config,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            temp_dir: std::env::temp_dir(),
+            dim: PhantomData,
+        }
+    }
+
+    /// Set the approximate number of bytes used for the candidates kept in memory.
+    /// Nodes whose candidates fit in the budget are built in memory.
+    pub fn with_memory_budget(mut self, memory_budget: usize) -> Self {
+        self.memory_budget = memory_budget;
+        self
+    }
+
+    /// Set the directory of the temporary files. They can take several times the size of
+    /// the bounding boxes of the scene on disk.
+    pub fn with_temp_dir<P: Into<PathBuf>>(mut self, temp_dir: P) -> Self {
+        self.temp_dir = temp_dir.into();
+        self
+    }
+
+    /// Build the tree of the shapes given their bounding boxes and write it to `writer`.
+    /// The index of a shape is its position in `bounds`.
+    ///
+    /// Wrap files in a `BufWriter`, the tree is written in small chunks.
+    ///
+    /// Fail with `InvalidInput` if `bounds` is empty.
+    pub fn build<I, W>(&self, bounds: I, writer: &mut W) -> io::Result<()>
+    where
+        I: IntoIterator<Item = AABB<T, D>>,
+        W: Write,
+    {
+        self.build_fallible(bounds.into_iter().map(Ok), writer)
+    }
+
+    /// Same as [build](#method.build) but the bounding boxes are read from `reader` until
+    /// its end. Each box is written as in [KDTree::write_to](struct.KDTree.html#method.write_to):
+    /// its minimum and then maximum coordinates, as little-endian floats of type `T`.
+    pub fn build_from_reader<R, W>(&self, reader: &mut R, writer: &mut W) -> io::Result<()>
+    where
+        R: Read,
+        W: Write,
+    {
+        let mut reader = BufReader::new(reader);
+        let bounds = iter::from_fn(|| match has_data_left(&mut reader) {
+            Ok(true) => Some(read_aabb(&mut reader)),
+            Ok(false) => None,
+            Err(err) => Some(Err(err)),
+        });
+        self.build_fallible(bounds, writer)
+    }
+
+    fn build_fallible<I, W>(&self, bounds: I, writer: &mut W) -> io::Result<()>
+    where
+        I: Iterator<Item = io::Result<AABB<T, D>>>,
+        W: Write,
+    {
+        // Sort the candidates of each axis by runs and keep the bounding boxes for the end
+        let (bounds_file, mut bounds_writer) = TempFile::create(&self.temp_dir)?;
+        let mut space = AABB::default();
+        let mut nb_shapes = 0;
+        let mut runs = RunWriter::new(self.max_events_in_memory());
+        for bb in bounds {
+            let bb = bb?;
+            space.merge(&bb);
+            write_aabb(&mut bounds_writer, &bb)?;
+            runs.push(&self.temp_dir, nb_shapes as u64, &bb)?;
+            nb_shapes += 1;
+        }
+        if nb_shapes == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no shapes"));
+        }
+        bounds_writer.flush()?;
+        drop(bounds_writer);
+        let axes = runs.finish(&self.temp_dir)?;
+
+        // Build the tree, writing its nodes
+        let mut tree_writer = TreeWriter::new::<T, D>(writer)?;
+        let node = NodeEvents {
+            axes,
+            nb_shapes,
+            float: PhantomData,
+        };
+        let progress = BuildProgress::new(&self.config);
+        let mut sides = vec![];
+        let root = self.build_node(&mut tree_writer, &progress, &space, node, &mut sides, 1.)?;
+        drop(sides);
+        progress.finish();
+
+        let mut bounds_reader = bounds_file.open()?;
+        let bounds = (0..nb_shapes).map(|_| read_aabb::<_, T, D>(&mut bounds_reader));
+        tree_writer.write_bounds(nb_shapes, bounds)?;
+        tree_writer.finish(root, &space)
+    }
+
+    /// Build the subtree of a node, write it and return the index of its root.
+    /// `sides` is the side of each shape of the tree in the last node split on disk.
+    fn build_node<W: Write>(
+        &self,
+        writer: &mut TreeWriter<W>,
+        progress: &BuildProgress,
+        space: &AABB<T, D>,
+        node: NodeEvents<T>,
+        sides: &mut Vec<Side>,
+        share: f64,
+    ) -> io::Result<usize> {
+        progress.check()?;
+        if node.nb_events() <= self.max_events_in_memory() {
+            let (candidates, shapes) = node.load()?;
+            let config = &self.config;
+            let (_, tree) = config
+                .threading()
+                .install(|| build_tree(config, progress, space, candidates, shapes, share))?;
+            return writer.write_subtree(tree);
+        }
+
+        // Same steps as `build_tree`, sweeping the files of the node
+        let nb_shapes = node.nb_shapes;
+        let mut search = SplitSearch::new(&self.config, nb_shapes, space);
+        for (dim, axis) in node.axes.iter().enumerate() {
+            let mut reader = axis.read::<T>()?;
+            search.sweep(Dimension(dim), reader.by_ref().map(|e| (e.pos, e.kind)));
+            reader.check()?;
+        }
+        let (cost, split, planar_side) = search.finish();
+
+        // Check that the cost of the splitting is not higher than the cost of the leaf.
+        if cost > leaf_cost(&self.config, nb_shapes) {
+            let mut reader = node.axes[Dimension::X.0].read::<T>()?;
+            let shapes = reader
+                .by_ref()
+                .filter(|e| e.kind != EventKind::End)
+                .map(|e| e.shape as usize)
+                .collect();
+            reader.check()?;
+            progress.finish_leaf(share);
+            return writer.write_node(&KDTreeNode::<T, D>::Leaf { shapes });
+        }
+
+        let (l_space, r_space) = split_space(space, &split);
+        let (left, right) = node.split(&self.temp_dir, &split, planar_side, sides)?;
+        let (l_share, r_share) = split_share(share, left.nb_shapes, right.nb_shapes);
+        let l_child = self.build_node(writer, progress, &l_space, left, sides, l_share)?;
+        let r_child = self.build_node(writer, progress, &r_space, right, sides, r_share)?;
+        writer.write_node(&KDTreeNode::Node {
+            l_child,
+            l_space,
+            r_child,
+            r_space,
+            split,
+        })
+    }
+
+    /// Number of candidates fitting in the memory budget.
+    fn max_events_in_memory(&self) -> usize {
+        self.memory_budget / mem::size_of::<Event<T>>()
+    }
+}
+
+impl<T: Float, D: Dim> Default for StreamingBuilder<T, D> {
+    fn default() -> Self {
+        Self::new(BuilderConfig::default())
+    }
+}
+
+/// Split candidate of a shape identified by its index in the tree.
+#[derive(Clone, Copy, Debug)]
+struct Event<T: Float> {
+    pos: T,
+    kind: EventKind,
+    shape: u64,
+}
+
+impl<T: Float> Event<T> {
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_float(writer, self.pos)?;
+        write_u8(writer, self.kind as u8)?;
+        write_u64(writer, self.shape)
+    }
+
+    fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let pos = read_float(reader)?;
+        let kind = match read_u8(reader)? {
+            0 => EventKind::End,
+            1 => EventKind::Planar,
+            2 => EventKind::Start,
+            _ => return Err(invalid_data("unknown event")),
+        };
+        let shape = read_u64(reader)?;
+        Ok(Event { pos, kind, shape })
+    }
+}
+
+impl<T: Float> Ord for Event<T> {
+    /// Same order as the candidates of the in-memory builder.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.pos
+            .total_cmp(&other.pos)
+            .then(self.kind.cmp(&other.kind))
+            .then(self.shape.cmp(&other.shape))
+    }
+}
+
+impl<T: Float> PartialOrd for Event<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Float> PartialEq for Event<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: Float> Eq for Event<T> {}
+
+/// Temporary file removed when dropped.
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Create a new temporary file in `dir` and return a writer to it.
+    fn create(dir: &Path) -> io::Result<(Self, BufWriter<File>)> {
+        loop {
+            let counter = TEMP_FILE_COUNTER.fetch_add(1, AtomicOrdering::Relaxed);
+            let path = dir.join(format!("kdtree-ray-{}-{counter}.tmp", process::id()));
+            match File::options().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((TempFile { path }, BufWriter::new(file))),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn open(&self) -> io::Result<BufReader<File>> {
+        Ok(BufReader::new(File::open(&self.path)?))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Sorted candidates of an axis stored in a temporary file.
+struct EventFile {
+    file: TempFile,
+    len: usize,
+}
+
+impl EventFile {
+    /// Write sorted events to a new temporary file.
+    fn create<T: Float>(dir: &Path, events: impl Iterator<Item = Event<T>>) -> io::Result<Self> {
+        let mut writer = EventWriter::create(dir)?;
+        for event in events {
+            writer.push(&event)?;
+        }
+        writer.finish()
+    }
+
+    fn read<T: Float>(&self) -> io::Result<EventReader<T>> {
+        Ok(EventReader {
+            reader: self.file.open()?,
+            remaining: self.len,
+            error: None,
+            float: PhantomData,
+        })
+    }
+}
+
+/// Writer of an [EventFile].
+struct EventWriter {
+    file: TempFile,
+    writer: BufWriter<File>,
+    len: usize,
+}
+
+impl EventWriter {
+    fn create(dir: &Path) -> io::Result<Self> {
+        let (file, writer) = TempFile::create(dir)?;
+        Ok(EventWriter {
+            file,
+            writer,
+            len: 0,
+        })
+    }
+
+    fn push<T: Float>(&mut self, event: &Event<T>) -> io::Result<()> {
+        self.len += 1;
+        event.write(&mut self.writer)
+    }
+
+    fn finish(mut self) -> io::Result<EventFile> {
+        self.writer.flush()?;
+        Ok(EventFile {
+            file: self.file,
+            len: self.len,
+        })
+    }
+}
+
+/// Iterator over the events of an [EventFile].
+///
+/// It stops at the first error, which is returned by [check](#method.check).
+struct EventReader<T: Float> {
+    reader: BufReader<File>,
+    remaining: usize,
+    error: Option<io::Error>,
+    float: PhantomData<T>,
+}
+
+impl<T: Float> EventReader<T> {
+    /// Return the error which stopped the iteration, if any.
+    fn check(self) -> io::Result<()> {
+        match self.error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn next_event(&mut self) -> io::Result<Option<Event<T>>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        Event::read(&mut self.reader).map(Some)
+    }
+}
+
+impl<T: Float> Iterator for EventReader<T> {
+    type Item = Event<T>;
+
+    fn next(&mut self) -> Option<Event<T>> {
+        if self.error.is_some() {
+            return None;
+        }
+        self.next_event().unwrap_or_else(|err| {
+            self.error = Some(err);
+            None
+        })
+    }
+}
+
+/// Sorted runs of candidates of each axis, spilled to disk when the buffers are full.
+struct RunWriter<T: Float> {
+    /// Candidates of each axis not spilled yet.
+    buffers: Vec<Vec<Event<T>>>,
+    /// Spilled runs of each axis.
+    runs: Vec<Vec<EventFile>>,
+    max_events: usize,
+}
+
+impl<T: Float> RunWriter<T> {
+    fn new(max_events: usize) -> Self {
+        RunWriter {
+            buffers: vec![],
+            runs: vec![],
+            max_events,
+        }
+    }
+
+    /// Add the candidates of a shape.
+    fn push<D: Dim>(&mut self, dir: &Path, shape: u64, bb: &AABB<T, D>) -> io::Result<()> {
+        self.buffers.resize_with(D::N, Vec::new);
+        self.runs.resize_with(D::N, Vec::new);
+        for (dim, buffer) in Dimension::all::<D>().zip(&mut self.buffers) {
+            buffer.extend(events(bb, dim).map(|(pos, kind)| Event { pos, kind, shape }));
+        }
+        if self.buffers.iter().map(Vec::len).sum::<usize>() >= self.max_events {
+            self.spill(dir)?;
+        }
+        Ok(())
+    }
+
+    /// Sort the buffers and write them as new runs.
+    fn spill(&mut self, dir: &Path) -> io::Result<()> {
+        for (buffer, runs) in self.buffers.iter_mut().zip(&mut self.runs) {
+            buffer.sort_unstable();
+            runs.push(EventFile::create(dir, buffer.drain(..))?);
+        }
+        Ok(())
+    }
+
+    /// Merge the runs and return the sorted candidates of each axis.
+    fn finish(mut self, dir: &Path) -> io::Result<Vec<EventFile>> {
+        self.spill(dir)?;
+        self.buffers = vec![];
+        self.runs
+            .into_iter()
+            .map(|runs| merge_runs::<T>(dir, runs))
+            .collect()
+    }
+}
+
+/// Merge sorted runs into a single file.
+fn merge_runs<T: Float>(dir: &Path, mut runs: Vec<EventFile>) -> io::Result<EventFile> {
+    if runs.len() == 1 {
+        return Ok(runs.pop().unwrap());
+    }
+    let mut readers = runs
+        .iter()
+        .map(EventFile::read::<T>)
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut heap = BinaryHeap::new();
+    for (run, reader) in readers.iter_mut().enumerate() {
+        if let Some(event) = reader.next_event()? {
+            heap.push(Reverse((event, run)));
+        }
+    }
+    let mut writer = EventWriter::create(dir)?;
+    while let Some(Reverse((event, run))) = heap.pop() {
+        writer.push(&event)?;
+        if let Some(event) = readers[run].next_event()? {
+            heap.push(Reverse((event, run)));
+        }
+    }
+    writer.finish()
+}
+
+/// Sorted candidates of a node stored on disk.
+struct NodeEvents<T: Float> {
+    axes: Vec<EventFile>,
+    nb_shapes: usize,
+    float: PhantomData<T>,
+}
+
+impl<T: Float> NodeEvents<T> {
+    fn nb_events(&self) -> usize {
+        self.axes.iter().map(|axis| axis.len).sum()
+    }
+
+    /// Load the candidates in memory, with the shapes numbered as in `build_tree`.
+    fn load(self) -> io::Result<(Candidates<T>, Vec<usize>)> {
+        assert!(self.nb_shapes <= u32::MAX as usize, "too many shapes");
+
+        // Shapes of the node sorted by index in the tree
+        let mut reader = self.axes[Dimension::X.0].read::<T>()?;
+        let mut shapes: Vec<usize> = reader
+            .by_ref()
+            .filter(|e| e.kind != EventKind::End)
+            .map(|e| e.shape as usize)
+            .collect();
+        reader.check()?;
+        shapes.sort_unstable();
+
+        let mut axes = Vec::with_capacity(self.axes.len());
+        for axis in &self.axes {
+            let mut reader = axis.read::<T>()?;
+            let candidates = reader
+                .by_ref()
+                .map(|e| Candidate {
+                    pos: e.pos,
+                    kind: e.kind,
+                    shape: shapes.binary_search(&(e.shape as usize)).unwrap() as u32,
+                })
+                .collect();
+            reader.check()?;
+            axes.push(candidates);
+        }
+        Ok((Candidates::from_axes(axes), shapes))
+    }
+
+    /// Split the candidates between the two children of the node.
+    fn split(
+        self,
+        dir: &Path,
+        split: &Plane<T>,
+        planar_side: Side,
+        sides: &mut Vec<Side>,
+    ) -> io::Result<(Self, Self)> {
+        // Classify the shapes, the events of a shape are sorted by position
+        let mut reader = self.axes[split.dimension.0].read::<T>()?;
+        for event in reader.by_ref() {
+            let shape = event.shape as usize;
+            if sides.len() <= shape {
+                sides.resize(shape + 1, Side::Both);
+            }
+            match classify_item(event.pos, event.kind, split, planar_side) {
+                Some(side) => sides[shape] = side,
+                None if event.kind == EventKind::Start => sides[shape] = Side::Both,
+                None => {}
+            }
+        }
+        reader.check()?;
+
+        let (mut l_axes, mut r_axes) = (vec![], vec![]);
+        let (mut l_shapes, mut r_shapes) = (0, 0);
+        for (dim, axis) in self.axes.iter().enumerate() {
+            let mut left = EventWriter::create(dir)?;
+            let mut right = EventWriter::create(dir)?;
+            let mut reader = axis.read::<T>()?;
+            while let Some(event) = reader.next_event()? {
+                let side = sides[event.shape as usize];
+                let is_first = event.kind != EventKind::End;
+                if !matches!(side, Side::Right) {
+                    left.push(&event)?;
+                    l_shapes += usize::from(dim == 0 && is_first);
+                }
+                if !matches!(side, Side::Left) {
+                    right.push(&event)?;
+                    r_shapes += usize::from(dim == 0 && is_first);
+                }
+            }
+            l_axes.push(left.finish()?);
+            r_axes.push(right.finish()?);
+        }
+        Ok((
+            NodeEvents {
+                axes: l_axes,
+                nb_shapes: l_shapes,
+                float: PhantomData,
+            },
+            NodeEvents {
+                axes: r_axes,
+                nb_shapes: r_shapes,
+                float: PhantomData,
+            },
+        ))
+    }
+}
+
+/// Check if there is data left in a reader.
+fn has_data_left<R: io::BufRead>(reader: &mut R) -> io::Result<bool> {
+    Ok(!reader.fill_buf()?.is_empty())
+}
@@ -0,0 +1,286 @@
+use crate::aabb::*;
+use crate::config::BuilderConfig;
+use crate::dim::D3;
+use crate::kdtree::KDTree;
+use crate::leaf::LeafData;
+use crate::ray::Ray;
+use crate::{Float, ToVector, Vector3};
+
+/// A triangle given by its three vertices.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Triangle<T: Float = f32> {
+    /// First vertex
+    pub a: Vector3<T>,
+    /// Second vertex
+    pub b: Vector3<T>,
+    /// Third vertex
+    pub c: Vector3<T>,
+}
+
+impl<T: Float> Triangle<T> {
+    /// Create a new triangle from its three vertices.
+    pub fn new<V: ToVector<T>>(a: &V, b: &V, c: &V) -> Self {
+        Triangle {
+            a: a.to_vector(),
+            b: b.to_vector(),
+            c: c.to_vector(),
+        }
+    }
+}
+
+impl<T: Float> Bounded<T> for Triangle<T> {
+    fn bound(&self) -> AABB<T> {
+        let mut bound = AABB::new(self.a, self.a);
+        bound.merge(&AABB::new(self.b, self.b));
+        bound.merge(&AABB::new(self.c, self.c));
+        bound
+    }
+}
+
+/// Closest intersection of a ray with the triangles of a
+/// [TriangleMesh](struct.TriangleMesh.html).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TriangleHit<T: Float = f32> {
+    /// Index of the triangle hit.
+    pub index: usize,
+    /// Distance along the ray to the hit, in units of the ray direction.
+    pub distance: T,
+    /// Barycentric coordinate of the hit along the second vertex: the point hit is
+    /// `(1 - u - v) * a + u * b + v * c`.
+    pub u: T,
+    /// Barycentric coordinate of the hit along the third vertex.
+    pub v: T,
+}
+
+/// `W` triangles precomputed for the intersection, stored as a structure of arrays so
+/// one ray is tested against the `W` triangles at once.
+///
+/// Unused lanes have a null normal and never intersect.
+#[derive(Clone, Debug)]
+struct TrianglePacket<T: Float, const W: usize> {
+    /// First vertex of each triangle, one array per axis.
+    origin: [[T; W]; 3],
+    /// Edges from the first vertex to the second and third ones.
+    edge1: [[T; W]; 3],
+    edge2: [[T; W]; 3],
+    /// Non normalized normals (`edge1 x edge2`).
+    normal: [[T; W]; 3],
+    indices: [usize; W],
+}
+
+impl<T: Float, const W: usize> TrianglePacket<T, W> {
+    /// Precompute up to `W` triangles with their index.
+    fn new<'a>(triangles: impl Iterator<Item = (usize, &'a Triangle<T>)>) -> Self {
+        let zero = [[T::zero(); W]; 3];
+        let mut packet = TrianglePacket {
+            origin: zero,
+            edge1: zero,
+            edge2: zero,
+            normal: zero,
+            indices: [usize::MAX; W],
+        };
+        for (lane, (index, triangle)) in triangles.take(W).enumerate() {
+            let edge1 = triangle.b - triangle.a;
+            let edge2 = triangle.c - triangle.a;
+            let normal = edge1.cross(edge2);
+            for axis in 0..3 {
+                packet.origin[axis][lane] = triangle.a[axis];
+                packet.edge1[axis][lane] = edge1[axis];
+                packet.edge2[axis][lane] = edge2[axis];
+                packet.normal[axis][lane] = normal[axis];
+            }
+            packet.indices[lane] = index;
+        }
+        packet
+    }
+
+    /// Intersect the triangles with a ray, keeping the hit closer than `closest`.
+    fn intersect(
+        &self,
+        origin: &Vector3<T>,
+        direction: &Vector3<T>,
+        closest: &mut Option<TriangleHit<T>>,
+    ) {
+        let max_distance = closest.map_or(T::infinity(), |hit| hit.distance);
+        let mut distance = [T::zero(); W];
+        let mut u = [T::zero(); W];
+        let mut v = [T::zero(); W];
+
+        // Every lane is computed without branching so the loops can be vectorized
+        for lane in 0..W {
+            // Vector from the first vertex to the ray origin
+            let s = [
+                origin.x - self.origin[0][lane],
+                origin.y - self.origin[1][lane],
+                origin.z - self.origin[2][lane],
+            ];
+            // s x direction
+            let q = [
+                s[1] * direction.z - s[2] * direction.y,
+                s[2] * direction.x - s[0] * direction.z,
+                s[0] * direction.y - s[1] * direction.x,
+            ];
+            let denominator = direction.x * self.normal[0][lane]
+                + direction.y * self.normal[1][lane]
+                + direction.z * self.normal[2][lane];
+            let inv = T::one() / denominator;
+            distance[lane] = -(s[0] * self.normal[0][lane]
+                + s[1] * self.normal[1][lane]
+                + s[2] * self.normal[2][lane])
+                * inv;
+            u[lane] = -(q[0] * self.edge2[0][lane]
+                + q[1] * self.edge2[1][lane]
+                + q[2] * self.edge2[2][lane])
+                * inv;
+            v[lane] = (q[0] * self.edge1[0][lane]
+                + q[1] * self.edge1[1][lane]
+                + q[2] * self.edge1[2][lane])
+                * inv;
+        }
+
+        let mut best = None;
+        let mut best_distance = max_distance;
+        for lane in 0..W {
+            // Comparisons are false for the NaNs of parallel rays and unused lanes
+            let hit = u[lane] >= T::zero()
+                && v[lane] >= T::zero()
+                && u[lane] + v[lane] <= T::one()
+                && distance[lane] > T::zero()
+                && distance[lane] < best_distance;
+            if hit {
+                best = Some(lane);
+                best_distance = distance[lane];
+            }
+        }
+        if let Some(lane) = best {
+            *closest = Some(TriangleHit {
+                index: self.indices[lane],
+                distance: distance[lane],
+                u: u[lane],
+                v: v[lane],
+            });
+        }
+    }
+}
+
+/// Leaf of a [TriangleMesh](struct.TriangleMesh.html): its triangles precomputed in
+/// packets of `W`, with their indices.
+#[derive(Clone, Debug)]
+pub struct TriangleLeaf<T: Float = f32, const W: usize = 4> {
+    packets: Vec<TrianglePacket<T, W>>,
+}
+
+impl<T: Float, const W: usize> TriangleLeaf<T, W> {
+    /// Precompute the packets of the triangles of a leaf given their indices.
+    fn new(shapes: Vec<usize>, triangles: &[Triangle<T>]) -> Self {
+        let packets = shapes
+            .chunks(W)
+            .map(|chunk| TrianglePacket::new(chunk.iter().map(|&index| (index, &triangles[index]))))
+            .collect();
+        TriangleLeaf { packets }
+    }
+
+    /// Indices of the triangles of the leaf.
+    pub fn shapes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.packets
+            .iter()
+            .flat_map(|packet| packet.indices)
+            .filter(|&index| index != usize::MAX)
+    }
+}
+
+impl<T: Float, const W: usize> LeafData for TriangleLeaf<T, W> {}
+
+/// KD-tree of triangles finding the closest triangle hit by a ray.
+///
+/// Each leaf stores its triangles precomputed (first vertex, edges and normal) in packets
+/// of `W` triangles, see [TriangleLeaf](struct.TriangleLeaf.html), laid out as structures
+/// of arrays, so a ray is tested against `W` triangles at once without looking up the
+/// triangles by index. Use `W = 4` or `W = 8` to match the width of SSE or AVX registers
+/// with `f32`.
+///
+/// ```
+/// use cgmath::Vector3;
+/// use kdtree_ray::{Triangle, TriangleMesh};
+///
+/// let triangles: Vec<Triangle> = (0..10)
+///     .map(|i| {
+///         let x = i as f32;
+///         Triangle::new(
+///             &Vector3::new(x, 0., 0.),
+///             &Vector3::new(x + 1., 0., 0.),
+///             &Vector3::new(x, 1., 0.),
+///         )
+///     })
+///     .collect();
+/// let mesh: TriangleMesh = TriangleMesh::build(triangles);
+/// let origin = Vector3::new(2.2, 0.2, 1.);
+/// let direction = Vector3::new(0., 0., -1.);
+/// let hit = mesh.intersect(&origin, &direction).unwrap();
+/// assert_eq!(hit.index, 2);
+/// assert_eq!(hit.distance, 1.);
+/// ```
+#[derive(Clone, Debug)]
+pub struct TriangleMesh<T: Float = f32, const W: usize = 4> {
+    tree: KDTree<T, D3, TriangleLeaf<T, W>>,
+    triangles: Vec<Triangle<T>>,
+}
+
+impl<T: Float, const W: usize> TriangleMesh<T, W> {
+    /// Build the KD-tree of the triangles given a configuration and precompute the
+    /// packets of its leaves.
+    /// Panic if `triangles` is empty.
+    pub fn build_config(triangles: Vec<Triangle<T>>, config: &BuilderConfig<T>) -> Self {
+        assert!(W > 0, "packets must hold at least one triangle");
+        let tree = KDTree::build_with_leaves(&triangles, config, |shapes| {
+            TriangleLeaf::new(shapes, &triangles)
+        });
+        TriangleMesh { tree, triangles }
+    }
+
+    /// Build the KD-tree of the triangles with a default configuration and precompute
+    /// the packets of its leaves.
+    /// Panic if `triangles` is empty.
+    pub fn build(triangles: Vec<Triangle<T>>) -> Self {
+        Self::build_config(triangles, &BuilderConfig::default())
+    }
+
+    /// Return the closest triangle hit by a ray, if any.
+    ///
+    /// Leaves are visited from the nearest to the farthest along the ray, and the
+    /// traversal stops at the first leaf beyond the closest hit found.
+    pub fn intersect<V: ToVector<T>>(
+        &self,
+        ray_origin: &V,
+        ray_direction: &V,
+    ) -> Option<TriangleHit<T>> {
+        let origin = ray_origin.to_vector();
+        let direction = ray_direction.to_vector();
+        let ray = Ray::new(ray_origin, ray_direction);
+        let mut closest = None;
+        self.tree
+            .visit_leaves_in_order(&ray, |leaf: &TriangleLeaf<T, W>| {
+                for packet in &leaf.packets {
+                    packet.intersect(&origin, &direction, &mut closest);
+                }
+                closest.map_or(T::infinity(), |hit: TriangleHit<T>| hit.distance)
+            });
+        closest
+    }
+
+    /// Retrieve the KD-tree of the triangles.
+    pub fn tree(&self) -> &KDTree<T, D3, TriangleLeaf<T, W>> {
+        &self.tree
+    }
+
+    /// Retrieve the triangles, in the order of their index.
+    pub fn triangles(&self) -> &[Triangle<T>] {
+        &self.triangles
+    }
+}
+
+impl<T: Float, const W: usize> Bounded<T> for TriangleMesh<T, W> {
+    fn bound(&self) -> AABB<T> {
+        self.tree.bound()
+    }
+}
@@ -0,0 +1,314 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::Float;
+use crate::aabb::*;
+use crate::candidate::Side;
+use crate::dim::Dim;
+use crate::kdnode::{KDTreeNode, shape_side, split_space};
+use crate::kdtree::KDTree;
+
+/// Invariant violated by a malformed KD-tree, returned by
+/// [KDTree::validate](struct.KDTree.html#method.validate).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The tree doesn't contain any node.
+    EmptyTree,
+    /// A node references a child index that doesn't exist.
+    ChildOutOfRange {
+        /// Index of the parent node.
+        node: usize,
+        /// Invalid child index.
+        child: usize,
+    },
+    /// A node is reachable more than once from the root (shared child or cycle).
+    NotATree {
+        /// Index of the node reached twice.
+        node: usize,
+    },
+    /// A node can't be reached from the root.
+    UnreachableNode {
+        /// Index of the unreachable node.
+        node: usize,
+    },
+    /// The space of a child isn't contained in the space of its parent.
+    ChildOutsideParent {
+        /// Index of the parent node.
+        node: usize,
+        /// Index of the child node.
+        child: usize,
+    },
+    /// A leaf references a shape index that doesn't exist.
+    ShapeOutOfRange {
+        /// Index of the leaf node.
+        leaf: usize,
+        /// Invalid shape index.
+        shape: usize,
+    },
+    /// A shape lies in the region of a leaf but isn't referenced by it.
+    MissingShape {
+        /// Index of the leaf node.
+        leaf: usize,
+        /// Index of the missing shape.
+        shape: usize,
+    },
+    /// The space of a leaf doesn't cover the part of a shape inside its region.
+    UncoveredShape {
+        /// Index of the leaf node.
+        leaf: usize,
+        /// Index of the uncovered shape.
+        shape: usize,
+    },
+    /// The bounding box of a shape isn't contained in the space of the root.
+    ShapeOutsideRoot {
+        /// Index of the shape.
+        shape: usize,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::EmptyTree => write!(f, "the tree doesn't contain any node"),
+            ValidationError::ChildOutOfRange { node, child } => {
+                write!(f, "node {node} references a non existing child {child}")
+            }
+            ValidationError::NotATree { node } => {
+                write!(f, "node {node} is reachable more than once from the root")
+            }
+            ValidationError::UnreachableNode { node } => {
+                write!(f, "node {node} isn't reachable from the root")
+            }
+            ValidationError::ChildOutsideParent { node, child } => {
+                write!(f, "space of node {child} isn't inside its parent {node}")
+            }
+            ValidationError::ShapeOutOfRange { leaf, shape } => {
+                write!(f, "leaf {leaf} references a non existing shape {shape}")
+            }
+            ValidationError::MissingShape { leaf, shape } => {
+                write!(
+                    f,
+                    "shape {shape} lies in leaf {leaf} but isn't referenced by it"
+                )
+            }
+            ValidationError::UncoveredShape { leaf, shape } => {
+                write!(f, "shape {shape} isn't covered by the space of leaf {leaf}")
+            }
+            ValidationError::ShapeOutsideRoot { shape } => {
+                write!(f, "shape {shape} isn't inside the space of the root")
+            }
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+impl<T: Float, D: Dim> KDTree<T, D> {
+    /// Check the invariants of the tree against the shapes it was built with (shapes removed
+    /// from the tree are ignored):
+    /// * Child indices are in range and form a tree.
+    /// * The space of each child is inside the space of its parent.
+    /// * Every shape lying in the region of a leaf (delimited by the splitting planes) is
+    ///   referenced by this leaf and covered by its space. Shapes touching a splitting plane
+    ///   belong to the side they lie on, shapes flat on the plane to either side.
+    /// * The space of the root contains every shape.
+    ///
+    /// This is meant for debugging and fuzzing, it's much slower than a query.
+    pub fn validate<S: Bounded<T, D>>(&self, shapes: &[S]) -> Result<(), ValidationError> {
+        self.check_nodes(0, shapes.len())?;
+
+        // Check the spaces of the tree
+        let mut regions = vec![AABB::empty(); self.tree.len()];
+        let mut spaces = vec![&self.space; self.tree.len()];
+        regions[0] = self.space.clone();
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            if let KDTreeNode::Node {
+                l_child,
+                l_space,
+                r_child,
+                r_space,
+                split,
+            } = &self.tree[index]
+            {
+                let (l_region, r_region) = split_space(&regions[index], split);
+                for (child, child_space, child_region) in
+                    [(*l_child, l_space, l_region), (*r_child, r_space, r_region)]
+                {
+                    if !spaces[index].contains(child_space) {
+                        return Err(ValidationError::ChildOutsideParent { node: index, child });
+                    }
+                    spaces[child] = child_space;
+                    regions[child] = child_region;
+                    stack.push(child);
+                }
+            }
+        }
+
+        // Check that shapes are referenced and covered by every leaf they lie in
+        let mut leaves = vec![];
+        for (shape, s) in shapes.iter().enumerate() {
+            if !self.contains_shape(shape) {
+                continue;
+            }
+            let bb = s.bound();
+            if !self.space.contains(&bb) {
+                return Err(ValidationError::ShapeOutsideRoot { shape });
+            }
+            leaves.clear();
+            let missing = self.missing_references(shape, &bb, |leaf| leaves.push(leaf));
+            if let Some(&leaf) = missing.first() {
+                return Err(ValidationError::MissingShape { leaf, shape });
+            }
+            for &leaf in &leaves {
+                let KDTreeNode::Leaf {
+                    shapes: leaf_shapes,
+                } = &self.tree[leaf]
+                else {
+                    unreachable!()
+                };
+                if leaf_shapes.contains(&shape)
+                    && !spaces[leaf].contains(&bb.intersection(&regions[leaf]))
+                {
+                    return Err(ValidationError::UncoveredShape { leaf, shape });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the nodes form a tree rooted at `root`, and that leaves reference shapes
+    /// with an index lower than `nb_shapes`.
+    pub(crate) fn check_nodes(&self, root: usize, nb_shapes: usize) -> Result<(), ValidationError> {
+        if self.tree.is_empty() {
+            return Err(ValidationError::EmptyTree);
+        }
+        let mut visited = vec![false; self.tree.len()];
+        visited[root] = true;
+        let mut stack = vec![root];
+        while let Some(index) = stack.pop() {
+            match &self.tree[index] {
+                KDTreeNode::Leaf { shapes } => {
+                    if let Some(&shape) = shapes.iter().find(|&&s| s >= nb_shapes) {
+                        return Err(ValidationError::ShapeOutOfRange { leaf: index, shape });
+                    }
+                }
+                KDTreeNode::Node {
+                    l_child, r_child, ..
+                } => {
+                    for child in [*l_child, *r_child] {
+                        if child >= self.tree.len() {
+                            return Err(ValidationError::ChildOutOfRange { node: index, child });
+                        }
+                        if visited[child] {
+                            return Err(ValidationError::NotATree { node: child });
+                        }
+                        visited[child] = true;
+                        stack.push(child);
+                    }
+                }
+            }
+        }
+        if let Some(node) = visited.iter().position(|v| !v) {
+            return Err(ValidationError::UnreachableNode { node });
+        }
+        Ok(())
+    }
+
+    /// Count the references missing from the leaves shapes lie in, and the stale references
+    /// of leaves shapes don't lie in anymore (after a refit).
+    pub(crate) fn count_references(&self) -> (usize, usize) {
+        let mut nb_references = 0;
+        for node in &self.tree {
+            if let KDTreeNode::Leaf { shapes } = node {
+                nb_references += shapes.len();
+            }
+        }
+        let mut nb_missing = 0;
+        let mut nb_found = 0;
+        for (shape, bb) in self.bounds.iter().enumerate() {
+            if bb.is_empty() {
+                continue;
+            }
+            let missing = self.missing_references(shape, bb, |leaf| {
+                if matches!(&self.tree[leaf], KDTreeNode::Leaf { shapes } if shapes.contains(&shape))
+                {
+                    nb_found += 1;
+                }
+            });
+            nb_missing += missing.len();
+        }
+        (nb_missing, nb_references - nb_found)
+    }
+
+    /// Find the leaves a shape lies in that don't reference it, following the splitting
+    /// planes the way the builder splits shapes. A shape flat on a splitting plane only has
+    /// to be referenced on one side, the side missing the fewest references is kept.
+    ///
+    /// `visit_leaf` is called with every leaf the shape lies in, on both sides of the planes
+    /// it's flat on.
+    pub(crate) fn missing_references<F: FnMut(usize)>(
+        &self,
+        shape: usize,
+        bb: &AABB<T, D>,
+        mut visit_leaf: F,
+    ) -> Vec<usize> {
+        enum Step {
+            Enter(usize),
+            /// Merge the leaves missing the shape in both children.
+            Both,
+            /// Keep the child missing the fewest references.
+            Either,
+        }
+        let mut steps = vec![Step::Enter(0)];
+        // Leaves missing the shape in the subtrees visited so far
+        let mut missing: Vec<Vec<usize>> = vec![];
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Enter(index) => match &self.tree[index] {
+                    KDTreeNode::Leaf { shapes } => {
+                        visit_leaf(index);
+                        if shapes.contains(&shape) {
+                            missing.push(vec![]);
+                        } else {
+                            missing.push(vec![index]);
+                        }
+                    }
+                    KDTreeNode::Node {
+                        l_child,
+                        r_child,
+                        split,
+                        ..
+                    } => match shape_side(bb, split) {
+                        Some(Side::Left) => steps.push(Step::Enter(*l_child)),
+                        Some(Side::Right) => steps.push(Step::Enter(*r_child)),
+                        side => {
+                            steps.push(match side {
+                                Some(_) => Step::Both,
+                                None => Step::Either,
+                            });
+                            steps.push(Step::Enter(*r_child));
+                            steps.push(Step::Enter(*l_child));
+                        }
+                    },
+                },
+                Step::Both => {
+                    let right = missing.pop().unwrap();
+                    let mut left = missing.pop().unwrap();
+                    left.extend(right);
+                    missing.push(left);
+                }
+                Step::Either => {
+                    let right = missing.pop().unwrap();
+                    let left = missing.pop().unwrap();
+                    missing.push(if right.len() < left.len() {
+                        right
+                    } else {
+                        left
+                    });
+                }
+            }
+        }
+        missing.pop().unwrap()
+    }
+}
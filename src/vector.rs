@@ -0,0 +1,154 @@
+use crate::dim::{D2, D3, Dim};
+use crate::{AABB, Float};
+
+/// Conversion into the vector type used by the crate for a space of dimension `D`
+/// (`cgmath::Vector3` in 3D, `cgmath::Vector2` in 2D).
+///
+/// Queries accept any type implementing this trait. AABBs are built from them with
+/// `AABB::from((min, max))`, while [AABB::new](struct.AABB.html#method.new) only takes the
+/// vector type of the space.
+///
+/// Implemented for:
+/// * `cgmath` vectors and points and arrays (`[T; 3]` or `[T; 2]`).
+/// * `glam::Vec3`, `glam::Vec3A`, `glam::DVec3`, `glam::Vec2` and `glam::DVec2` with the
+///   `glam` feature.
+/// * `nalgebra` vectors and points with the `nalgebra` feature.
+/// * `mint` vectors and points with the `mint` feature.
+pub trait ToVector<T: Float, D: Dim = D3> {
+    /// Convert into the vector type of the space.
+    fn to_vector(&self) -> D::Vector<T>;
+}
+
+impl<T: Float> ToVector<T, D3> for cgmath::Vector3<T> {
+    fn to_vector(&self) -> cgmath::Vector3<T> {
+        *self
+    }
+}
+
+impl<T: Float> ToVector<T, D3> for cgmath::Point3<T> {
+    fn to_vector(&self) -> cgmath::Vector3<T> {
+        cgmath::Vector3::new(self.x, self.y, self.z)
+    }
+}
+
+impl<T: Float> ToVector<T, D3> for [T; 3] {
+    fn to_vector(&self) -> cgmath::Vector3<T> {
+        cgmath::Vector3::new(self[0], self[1], self[2])
+    }
+}
+
+#[cfg(feature = "glam")]
+impl ToVector<f32, D3> for glam::Vec3 {
+    fn to_vector(&self) -> cgmath::Vector3<f32> {
+        cgmath::Vector3::new(self.x, self.y, self.z)
+    }
+}
+
+#[cfg(feature = "glam")]
+impl ToVector<f32, D3> for glam::Vec3A {
+    fn to_vector(&self) -> cgmath::Vector3<f32> {
+        cgmath::Vector3::new(self.x, self.y, self.z)
+    }
+}
+
+#[cfg(feature = "glam")]
+impl ToVector<f64, D3> for glam::DVec3 {
+    fn to_vector(&self) -> cgmath::Vector3<f64> {
+        cgmath::Vector3::new(self.x, self.y, self.z)
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<T: Float + nalgebra::Scalar> ToVector<T, D3> for nalgebra::Vector3<T> {
+    fn to_vector(&self) -> cgmath::Vector3<T> {
+        cgmath::Vector3::new(self.x, self.y, self.z)
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<T: Float + nalgebra::Scalar> ToVector<T, D3> for nalgebra::Point3<T> {
+    fn to_vector(&self) -> cgmath::Vector3<T> {
+        cgmath::Vector3::new(self.x, self.y, self.z)
+    }
+}
+
+#[cfg(feature = "mint")]
+impl<T: Float> ToVector<T, D3> for mint::Vector3<T> {
+    fn to_vector(&self) -> cgmath::Vector3<T> {
+        cgmath::Vector3::new(self.x, self.y, self.z)
+    }
+}
+
+#[cfg(feature = "mint")]
+impl<T: Float> ToVector<T, D3> for mint::Point3<T> {
+    fn to_vector(&self) -> cgmath::Vector3<T> {
+        cgmath::Vector3::new(self.x, self.y, self.z)
+    }
+}
+
+impl<T: Float> ToVector<T, D2> for cgmath::Vector2<T> {
+    fn to_vector(&self) -> cgmath::Vector2<T> {
+        *self
+    }
+}
+
+impl<T: Float> ToVector<T, D2> for cgmath::Point2<T> {
+    fn to_vector(&self) -> cgmath::Vector2<T> {
+        cgmath::Vector2::new(self.x, self.y)
+    }
+}
+
+impl<T: Float> ToVector<T, D2> for [T; 2] {
+    fn to_vector(&self) -> cgmath::Vector2<T> {
+        cgmath::Vector2::new(self[0], self[1])
+    }
+}
+
+#[cfg(feature = "glam")]
+impl ToVector<f32, D2> for glam::Vec2 {
+    fn to_vector(&self) -> cgmath::Vector2<f32> {
+        cgmath::Vector2::new(self.x, self.y)
+    }
+}
+
+#[cfg(feature = "glam")]
+impl ToVector<f64, D2> for glam::DVec2 {
+    fn to_vector(&self) -> cgmath::Vector2<f64> {
+        cgmath::Vector2::new(self.x, self.y)
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<T: Float + nalgebra::Scalar> ToVector<T, D2> for nalgebra::Vector2<T> {
+    fn to_vector(&self) -> cgmath::Vector2<T> {
+        cgmath::Vector2::new(self.x, self.y)
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<T: Float + nalgebra::Scalar> ToVector<T, D2> for nalgebra::Point2<T> {
+    fn to_vector(&self) -> cgmath::Vector2<T> {
+        cgmath::Vector2::new(self.x, self.y)
+    }
+}
+
+#[cfg(feature = "mint")]
+impl<T: Float> ToVector<T, D2> for mint::Vector2<T> {
+    fn to_vector(&self) -> cgmath::Vector2<T> {
+        cgmath::Vector2::new(self.x, self.y)
+    }
+}
+
+#[cfg(feature = "mint")]
+impl<T: Float> ToVector<T, D2> for mint::Point2<T> {
+    fn to_vector(&self) -> cgmath::Vector2<T> {
+        cgmath::Vector2::new(self.x, self.y)
+    }
+}
+
+impl<T: Float, D: Dim, V: ToVector<T, D>> From<(V, V)> for AABB<T, D> {
+    /// Create an AABB from a `(min, max)` pair of vectors.
+    fn from((min, max): (V, V)) -> Self {
+        AABB::new(min.to_vector(), max.to_vector())
+    }
+}
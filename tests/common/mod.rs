@@ -0,0 +1,167 @@
+//! Scenes and rays shared by the integration tests and the benchmarks.
+#![allow(dead_code)]
+
+use cgmath::Vector3;
+use kdtree_ray::AABB;
+
+/// Small deterministic pseudo-random generator (values between `0.` and `1.`).
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next(&mut self) -> f32 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 40) as f32 / (1 << 24) as f32
+    }
+
+    /// Random vector with coordinates between `0.` and `scale`.
+    pub fn vector(&mut self, scale: f32) -> Vector3<f32> {
+        Vector3::new(self.next(), self.next(), self.next()) * scale
+    }
+}
+
+/// Random boxes in a cube of side `100.`.
+pub fn random_boxes(nb_boxes: usize, seed: u64) -> Vec<AABB> {
+    let mut rng = Rng(seed);
+    (0..nb_boxes)
+        .map(|_| {
+            let min = rng.vector(100.);
+            let size = rng.vector(1.);
+            AABB::new(min, min + size)
+        })
+        .collect()
+}
+
+/// Random boxes, with many of them sharing the same positions or flat on an axis.
+pub fn aligned_boxes(nb_boxes: usize, seed: u64) -> Vec<AABB> {
+    let mut rng = Rng(seed);
+    (0..nb_boxes)
+        .map(|_| {
+            let min = Vector3::new(
+                (rng.next() * 50.).floor(),
+                (rng.next() * 50.).floor(),
+                rng.next() * 50.,
+            );
+            let size = Vector3::new((rng.next() * 3.).floor(), rng.next() * 3., rng.next());
+            AABB::new(min, min + size)
+        })
+        .collect()
+}
+
+/// Random rays crossing a cube of side `100.`.
+pub fn rays(nb_rays: usize, seed: u64) -> Vec<(Vector3<f32>, Vector3<f32>)> {
+    let mut rng = Rng(seed);
+    (0..nb_rays)
+        .map(|_| {
+            let target = rng.vector(100.);
+            let direction = rng.vector(1.) - Vector3::new(0.5, 0.5, 0.5);
+            (target - direction * 200., direction)
+        })
+        .collect()
+}
+
+/// Check if a ray hits a box, with the same arithmetic as the tree traversal.
+pub fn hits(bb: &AABB, origin: &Vector3<f32>, direction: &Vector3<f32>) -> bool {
+    let mut t_min = f32::NEG_INFINITY;
+    let mut t_max = f32::INFINITY;
+    for axis in 0..3 {
+        let inv = 1. / direction[axis];
+        let t1 = (bb.min[axis] - origin[axis]) * inv;
+        let t2 = (bb.max[axis] - origin[axis]) * inv;
+        t_min = t_min.max(t1.min(t2));
+        t_max = t_max.min(t1.max(t2));
+    }
+    t_min <= t_max && t_max > 0.
+}
+
+/// Indices of the boxes hit by a ray, by testing every box.
+pub fn brute_force(boxes: &[AABB], origin: &Vector3<f32>, direction: &Vector3<f32>) -> Vec<usize> {
+    (0..boxes.len())
+        .filter(|&index| hits(&boxes[index], origin, direction))
+        .collect()
+}
+
+/// Unit boxes touching each other in a row along the X axis, from `x = 0.`.
+pub fn row_of_boxes(nb_boxes: usize) -> Vec<AABB> {
+    (0..nb_boxes)
+        .map(|i| {
+            let min = Vector3::new(i as f32, 0., 0.);
+            AABB::new(min, min + Vector3::new(1., 1., 1.))
+        })
+        .collect()
+}
+
+/// Records of a file in the format of `KDTree::write_to` for a 3D tree of `f32`, to write
+/// trees of a known shape. Every space is the unit cube unless told otherwise.
+pub struct TreeFile(Vec<u8>);
+
+impl TreeFile {
+    pub fn new() -> Self {
+        let mut file = TreeFile(b"KDTR".to_vec());
+        for value in [1u32, 4, 3] {
+            file.0.extend(value.to_le_bytes());
+        }
+        file
+    }
+
+    fn aabb(&mut self) {
+        self.aabb_of([0., 0., 0.], [1., 1., 1.]);
+    }
+
+    fn aabb_of(&mut self, min: [f32; 3], max: [f32; 3]) {
+        for value in min.into_iter().chain(max) {
+            self.0.extend(value.to_le_bytes());
+        }
+    }
+
+    pub fn leaf(mut self, shapes: &[u64]) -> Self {
+        self.0.push(0);
+        self.0.extend((shapes.len() as u64).to_le_bytes());
+        for shape in shapes {
+            self.0.extend(shape.to_le_bytes());
+        }
+        self
+    }
+
+    /// Node split on the X axis at `x = 0.5`.
+    pub fn node(self, l_child: u64, r_child: u64) -> Self {
+        self.node_with_spaces(l_child, r_child, [1., 1., 1.])
+    }
+
+    /// Node split on the X axis at `x = 0.5` whose children spaces go from the origin to
+    /// `max`.
+    pub fn node_with_spaces(mut self, l_child: u64, r_child: u64, max: [f32; 3]) -> Self {
+        self.0.push(1);
+        self.0.extend(0u32.to_le_bytes());
+        self.0.extend(0.5f32.to_le_bytes());
+        self.0.extend(l_child.to_le_bytes());
+        self.aabb_of([0., 0., 0.], max);
+        self.0.extend(r_child.to_le_bytes());
+        self.aabb_of([0., 0., 0.], max);
+        self
+    }
+
+    /// Bounding boxes of `nb_shapes` shapes.
+    pub fn bounds(mut self, nb_shapes: u64) -> Self {
+        self.0.push(2);
+        self.0.extend(nb_shapes.to_le_bytes());
+        for _ in 0..nb_shapes {
+            self.aabb();
+        }
+        self
+    }
+
+    pub fn footer(self, root: u64) -> Vec<u8> {
+        self.footer_with_space(root, [0., 0., 0.], [1., 1., 1.])
+    }
+
+    /// Footer with a space of the tree other than the unit cube.
+    pub fn footer_with_space(mut self, root: u64, min: [f32; 3], max: [f32; 3]) -> Vec<u8> {
+        self.0.push(3);
+        self.0.extend(root.to_le_bytes());
+        self.aabb_of(min, max);
+        self.0
+    }
+}
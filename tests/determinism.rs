@@ -0,0 +1,63 @@
+//! Builds must give the same tree whatever the threads used.
+
+mod common;
+
+use common::aligned_boxes;
+use kdtree_ray::{BuilderConfig, KDTree, Threading};
+
+#[test]
+fn sequential_and_parallel_builds_are_equal() {
+    let shapes = aligned_boxes(5000, 1);
+    let reference = KDTree::build_config(
+        &shapes,
+        &BuilderConfig::default().with_threading(Threading::Sequential),
+    );
+    for parallel_threshold in [0, 16, 1024] {
+        let config = BuilderConfig::default().with_parallel_threshold(parallel_threshold);
+        let tree = KDTree::build_config(&shapes, &config);
+        assert!(tree == reference);
+        assert_eq!(tree.fingerprint(), reference.fingerprint());
+    }
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn builds_are_equal_whatever_the_number_of_threads() {
+    use std::sync::Arc;
+
+    let shapes = aligned_boxes(5000, 1);
+    let fingerprints: Vec<u64> = [1, 2, 3, 8]
+        .into_iter()
+        .map(|num_threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(num_threads)
+                .build()
+                .unwrap();
+            let config = BuilderConfig::default()
+                .with_threading(Threading::Pool(Arc::new(pool)))
+                .with_parallel_threshold(0);
+            KDTree::build_config(&shapes, &config).fingerprint()
+        })
+        .collect();
+    assert!(fingerprints.iter().all(|&f| f == fingerprints[0]));
+}
+
+#[test]
+fn fingerprint_is_stable() {
+    let tree = KDTree::build(&aligned_boxes(200, 1));
+    assert_eq!(tree.fingerprint(), 17748037547073998320);
+}
+
+#[test]
+fn different_trees_have_different_fingerprints() {
+    let shapes = aligned_boxes(200, 1);
+    let tree = KDTree::build(&shapes);
+    let other = KDTree::build(&shapes[1..]);
+    assert!(tree != other);
+    assert_ne!(tree.fingerprint(), other.fingerprint());
+
+    let config = BuilderConfig::new(1., 20., 0.2);
+    let other = KDTree::build_config(&shapes, &config);
+    assert!(tree != other);
+    assert_ne!(tree.fingerprint(), other.fingerprint());
+}
@@ -0,0 +1,176 @@
+//! Edited trees must stay valid and answer queries like trees built from scratch.
+
+mod common;
+
+use cgmath::Vector3;
+use common::{TreeFile, brute_force, hits, random_boxes, rays, row_of_boxes};
+use kdtree_ray::{AABB, KDTree};
+
+/// Check that the edited tree returns the same boxes hit by random rays as a fresh build of
+/// the boxes still in the tree.
+fn check_queries(tree: &KDTree, boxes: &[AABB], removed: &[usize]) {
+    let kept: Vec<usize> = (0..boxes.len()).filter(|i| !removed.contains(i)).collect();
+    let kept_boxes: Vec<AABB> = kept.iter().map(|&i| boxes[i].clone()).collect();
+    let fresh = KDTree::build(&kept_boxes);
+    for (origin, direction) in rays(500, 8) {
+        let mut result: Vec<usize> = tree
+            .intersect(&origin, &direction)
+            .into_iter()
+            .filter(|&i| hits(&boxes[i], &origin, &direction))
+            .collect();
+        result.sort();
+        let expected: Vec<usize> = fresh
+            .intersect(&origin, &direction)
+            .into_iter()
+            .filter(|&i| hits(&kept_boxes[i], &origin, &direction))
+            .map(|i| kept[i])
+            .collect();
+        assert_eq!(result, expected);
+        assert!(result.iter().all(|i| !removed.contains(i)));
+    }
+}
+
+#[test]
+fn inserted_shapes_are_found() {
+    let boxes = random_boxes(3000, 21);
+    let mut tree = KDTree::build(&boxes[..1000]);
+    for (index, bb) in boxes.iter().enumerate().skip(1000) {
+        tree.insert(index, bb);
+    }
+    assert_eq!(tree.validate(&boxes), Ok(()));
+    assert!(tree.stats().max_depth > 5);
+    check_queries(&tree, &boxes, &[]);
+}
+
+#[test]
+fn removed_shapes_are_not_returned() {
+    let boxes = random_boxes(2000, 22);
+    let mut tree = KDTree::build(&boxes);
+    let removed: Vec<usize> = (0..boxes.len()).step_by(3).collect();
+    for &index in &removed {
+        assert!(tree.remove(index));
+    }
+    assert!(!tree.remove(removed[0]));
+    assert_eq!(tree.validate(&boxes), Ok(()));
+    check_queries(&tree, &boxes, &removed);
+
+    // Removed shapes can be inserted again
+    for &index in &removed {
+        tree.insert(index, &boxes[index]);
+    }
+    assert_eq!(tree.validate(&boxes), Ok(()));
+    check_queries(&tree, &boxes, &[]);
+}
+
+#[test]
+fn shapes_on_splitting_planes_are_inserted_and_removed() {
+    // Boxes of the row touch each other on the splitting planes
+    let mut boxes = row_of_boxes(32);
+    let mut tree = KDTree::build(&boxes);
+    for x in [0., 4., 8., 8., 16., 31., 32.] {
+        // Quads and points lying in the planes x = 0, 4, ...
+        boxes.push(AABB::new(Vector3::new(x, 0., 0.), Vector3::new(x, 1., 1.)));
+        boxes.push(AABB::new(
+            Vector3::new(x, 0.5, 0.5),
+            Vector3::new(x, 0.5, 0.5),
+        ));
+    }
+    for (index, bb) in boxes.iter().enumerate().skip(32) {
+        tree.insert(index, bb);
+    }
+    assert_eq!(tree.validate(&boxes), Ok(()));
+    let origin = Vector3::new(-1., 0.5, 0.5);
+    let direction = Vector3::new(1., 0., 0.);
+    assert_eq!(
+        tree.intersect(&origin, &direction),
+        brute_force(&boxes, &origin, &direction)
+    );
+
+    let removed: Vec<usize> = (32..boxes.len()).collect();
+    for &index in &removed {
+        assert!(tree.remove(index));
+    }
+    assert_eq!(tree.validate(&boxes), Ok(()));
+    assert_eq!(
+        tree.intersect(&origin, &direction),
+        (0..32).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn removals_collapse_empty_subtrees() {
+    let boxes = row_of_boxes(64);
+    let mut tree = KDTree::build(&boxes);
+    let nb_nodes = tree.stats().nb_nodes;
+
+    // Empty the left half of the row
+    let removed: Vec<usize> = (0..32).collect();
+    for &index in &removed {
+        tree.remove(index);
+    }
+    let stats = tree.stats();
+    assert!(stats.nb_nodes < nb_nodes);
+    assert_eq!(stats.nb_unique_shapes, 32);
+    assert_eq!(tree.validate(&boxes), Ok(()));
+    check_queries(&tree, &boxes, &removed);
+
+    // Removing every shape leaves a single empty leaf
+    for index in 32..64 {
+        tree.remove(index);
+    }
+    let stats = tree.stats();
+    assert_eq!(stats.nb_nodes, 1);
+    assert_eq!(stats.nb_empty_leaves, 1);
+    assert!(
+        tree.intersect(&Vector3::new(-1., 0.5, 0.5), &Vector3::new(1., 0., 0.))
+            .is_empty()
+    );
+}
+
+#[test]
+fn unsplittable_leaves_keep_growing() {
+    // Shapes on top of each other can't be separated by a split
+    let bb = AABB::new(Vector3::new(0., 0., 0.), Vector3::new(1., 1., 1.));
+    let mut boxes = row_of_boxes(8);
+    let mut tree = KDTree::build(&boxes);
+    for index in 8..108 {
+        boxes.push(bb.clone());
+        tree.insert(index, &bb);
+    }
+    assert_eq!(tree.validate(&boxes), Ok(()));
+    let result = tree.intersect(&Vector3::new(0.5, -1., 0.5), &Vector3::new(0., 1., 0.));
+    assert!((8..108).all(|index| result.contains(&index)));
+}
+
+#[test]
+fn shapes_are_removed_from_deep_trees() {
+    // Each node has an empty leaf on the left and the rest of the tree on the right
+    let depth = 100_000;
+    let mut file = TreeFile::new().leaf(&[0]);
+    for i in 0..depth {
+        file = file.leaf(&[]).node(2 * i + 1, 2 * i);
+    }
+    let file = file.bounds(1).footer(2 * depth);
+    let mut tree: KDTree = KDTree::read_from(&mut file.as_slice()).unwrap();
+    assert!(tree.remove(0));
+    assert_eq!(tree.stats().nb_nodes, 1);
+}
+
+#[test]
+fn stale_references_of_written_trees_are_removed() {
+    let boxes = row_of_boxes(64);
+    let mut moved = boxes.clone();
+    moved[3] = AABB::new(Vector3::new(40.2, 0., 0.), Vector3::new(40.8, 1., 1.));
+    let mut tree = KDTree::build(&boxes);
+    assert!(tree.refit(&moved).nb_stale_references > 0);
+
+    let mut file = vec![];
+    tree.write_to(&mut file).unwrap();
+    let mut tree: KDTree = KDTree::read_from(&mut file.as_slice()).unwrap();
+    assert!(tree.remove(3));
+    let direction = Vector3::new(0., 1., 0.);
+    for x in 0..64 {
+        let origin = Vector3::new(x as f32 + 0.5, -1., 0.5);
+        assert!(!tree.intersect(&origin, &direction).contains(&3));
+    }
+}
@@ -0,0 +1,53 @@
+//! Exported wireframes and graphs must describe every node of the tree.
+
+mod common;
+
+use common::row_of_boxes;
+use kdtree_ray::KDTree;
+
+fn count_lines(text: &str, prefix: &str) -> usize {
+    text.lines().filter(|line| line.starts_with(prefix)).count()
+}
+
+#[test]
+fn obj_export_has_a_box_per_node() {
+    let tree = KDTree::build(&row_of_boxes(16));
+    let stats = tree.stats();
+    assert!(stats.nb_nodes > 1);
+
+    let mut obj = vec![];
+    tree.export_obj(&mut obj, usize::MAX).unwrap();
+    let obj = String::from_utf8(obj).unwrap();
+    assert_eq!(count_lines(&obj, "v "), 8 * stats.nb_nodes);
+    assert_eq!(count_lines(&obj, "l "), 12 * stats.nb_nodes);
+    assert_eq!(count_lines(&obj, "g "), stats.max_depth + 1);
+
+    // Only the root
+    let mut obj = vec![];
+    tree.export_obj(&mut obj, 0).unwrap();
+    let obj = String::from_utf8(obj).unwrap();
+    assert_eq!(count_lines(&obj, "v "), 8);
+    assert_eq!(count_lines(&obj, "l "), 12);
+    assert!(obj.contains("l 1 5\n"));
+    assert!(obj.contains("l 8 7\n"));
+    assert!(obj.contains("v 0 0 0\n"));
+    assert!(obj.contains("v 16 1 1\n"));
+}
+
+#[test]
+fn dot_export_has_a_vertex_per_node_and_an_edge_per_child() {
+    let tree = KDTree::build(&row_of_boxes(16));
+    let stats = tree.stats();
+
+    let mut dot = vec![];
+    tree.export_dot(&mut dot).unwrap();
+    let dot = String::from_utf8(dot).unwrap();
+    assert!(dot.starts_with("digraph kdtree {\n"));
+    assert!(dot.ends_with("}\n"));
+    let nb_edges = dot.lines().filter(|line| line.contains("->")).count();
+    let nb_vertices = dot.lines().filter(|line| line.contains("[")).count() - nb_edges;
+    assert_eq!(nb_vertices, stats.nb_nodes);
+    assert_eq!(nb_edges, 2 * (stats.nb_nodes - stats.nb_leaves));
+    assert_eq!(count_lines(&dot, "  n0 [label=\"X = "), 1);
+    assert_eq!(dot.matches("shape=box").count(), stats.nb_leaves);
+}
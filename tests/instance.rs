@@ -0,0 +1,75 @@
+//! Instanced queries must return every shape hit once transformed into world space.
+
+mod common;
+
+use std::sync::Arc;
+
+use cgmath::{Deg, Matrix4, Vector3};
+use common::{hits, random_boxes, rays};
+use kdtree_ray::{AABB, Instance, InstanceTree, KDTree};
+
+/// World space bounding box of a transformed box, shrunk by `margin` to skip the rays
+/// grazing it, where the world and object space computations can disagree.
+fn transform_box(transform: &Matrix4<f32>, bb: &AABB, margin: f32) -> AABB {
+    let mut result: AABB = AABB::empty();
+    for i in 0..8 {
+        let corner = Vector3::new(
+            if i & 4 == 0 { bb.min.x } else { bb.max.x },
+            if i & 2 == 0 { bb.min.y } else { bb.max.y },
+            if i & 1 == 0 { bb.min.z } else { bb.max.z },
+        );
+        let corner = (transform * corner.extend(1.)).truncate();
+        result.merge(&AABB::new(corner, corner));
+    }
+    let margin = Vector3::new(margin, margin, margin);
+    AABB::new(result.min + margin, result.max - margin)
+}
+
+#[test]
+fn instanced_queries_find_the_transformed_boxes_hit() {
+    let boxes = random_boxes(2000, 31);
+    let tree = Arc::new(KDTree::build(&boxes));
+    // Rotations by right angles keep the transformed boxes tight
+    let transforms = [
+        Matrix4::from_scale(1.),
+        Matrix4::from_translation(Vector3::new(20., -10., 5.)) * Matrix4::from_scale(0.5),
+        Matrix4::from_translation(Vector3::new(100., 0., 0.)) * Matrix4::from_angle_z(Deg(90.)),
+        Matrix4::from_translation(Vector3::new(0., 50., 100.))
+            * Matrix4::from_angle_x(Deg(-90.))
+            * Matrix4::from_nonuniform_scale(0.25, 1., 0.5),
+    ];
+    let scene = InstanceTree::build(
+        transforms
+            .iter()
+            .map(|transform| Instance::new(tree.clone(), *transform))
+            .collect(),
+    );
+    let world_boxes: Vec<Vec<AABB>> = transforms
+        .iter()
+        .map(|transform| {
+            boxes
+                .iter()
+                .map(|bb| transform_box(transform, bb, 1e-3))
+                .collect()
+        })
+        .collect();
+
+    let mut nb_hits = 0;
+    for (origin, direction) in rays(1000, 32) {
+        let result = scene.intersect(&origin, &direction);
+        for (instance, world_boxes) in world_boxes.iter().enumerate() {
+            for (shape, bb) in world_boxes.iter().enumerate() {
+                if hits(bb, &origin, &direction) {
+                    nb_hits += 1;
+                    assert!(result.contains(&(instance, shape)));
+                }
+            }
+        }
+        // Every pair comes from the tree of its instance
+        for &(instance, shape) in &result {
+            let candidates = scene.instances()[instance].intersect(&origin, &direction);
+            assert!(candidates.contains(&shape));
+        }
+    }
+    assert!(nb_hits > 100);
+}
@@ -0,0 +1,66 @@
+//! Lazy trees must answer queries like full trees, and become the same tree once built.
+
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use common::{random_boxes, rays};
+use kdtree_ray::{BuilderConfig, KDTree, LazyKDTree};
+
+#[test]
+fn lazy_queries_are_the_same_as_full_queries() {
+    let shapes = random_boxes(20000, 11);
+    let tree = KDTree::build(&shapes);
+    let lazy = LazyKDTree::build(&shapes);
+    assert!(!lazy.is_materialized());
+
+    // Concurrent queries entering the same regions
+    thread::scope(|scope| {
+        for seed in 0..4 {
+            let (tree, lazy) = (&tree, &lazy);
+            scope.spawn(move || {
+                for (origin, direction) in rays(200, seed) {
+                    assert_eq!(
+                        lazy.intersect(&origin, &direction),
+                        tree.intersect(&origin, &direction)
+                    );
+                }
+            });
+        }
+    });
+
+    let lazy = lazy.into_kdtree();
+    assert!(lazy == tree);
+    assert_eq!(lazy.validate(&shapes), Ok(()));
+}
+
+#[test]
+fn materialized_trees_are_the_same_as_full_trees() {
+    let shapes = random_boxes(5000, 11);
+    let lazy = LazyKDTree::build(&shapes);
+    lazy.materialize();
+    assert!(lazy.is_materialized());
+    let tree = lazy.into_kdtree();
+    assert!(tree == KDTree::build(&shapes));
+    assert_eq!(tree.fingerprint(), KDTree::build(&shapes).fingerprint());
+}
+
+#[test]
+fn lazy_trees_ignore_the_progress_callback() {
+    let shapes = random_boxes(20000, 13);
+    let nb_reports = Arc::new(AtomicUsize::new(0));
+    let config = {
+        let nb_reports = nb_reports.clone();
+        BuilderConfig::default().with_progress(move |_| {
+            nb_reports.fetch_add(1, Ordering::Relaxed);
+        })
+    };
+    let lazy = LazyKDTree::build_config(&shapes, &config);
+    drop(config);
+    assert_eq!(Arc::strong_count(&nb_reports), 1);
+
+    lazy.into_kdtree();
+    assert_eq!(nb_reports.load(Ordering::Relaxed), 0);
+}
@@ -0,0 +1,83 @@
+//! Motion queries must return every shape whose interpolated box is hit at the time of the ray.
+
+mod common;
+
+use cgmath::Vector3;
+use common::{Rng, hits, rays};
+use kdtree_ray::{AABB, MotionBounded, MotionKDTree};
+
+/// Box moving linearly between two keyframes.
+struct Moving(AABB, AABB);
+
+impl MotionBounded for Moving {
+    fn bound_start(&self) -> AABB {
+        self.0.clone()
+    }
+
+    fn bound_end(&self) -> AABB {
+        self.1.clone()
+    }
+}
+
+fn moving_boxes(nb_boxes: usize) -> Vec<Moving> {
+    let mut rng = Rng(41);
+    (0..nb_boxes)
+        .map(|_| {
+            let min = rng.vector(100.);
+            let start = AABB::new(min, min + rng.vector(1.));
+            let offset = rng.vector(20.) - Vector3::new(10., 10., 10.);
+            let size = rng.vector(1.);
+            Moving(start, AABB::new(min + offset, min + offset + size))
+        })
+        .collect()
+}
+
+/// Box of a shape at a time, shrunk to skip the rays grazing it.
+fn bound_at(shape: &Moving, time: f32) -> AABB {
+    let bb = shape.0.lerp(&shape.1, time);
+    let margin = Vector3::new(1e-3, 1e-3, 1e-3);
+    AABB::new(bb.min + margin, bb.max - margin)
+}
+
+#[test]
+fn motion_queries_find_the_interpolated_boxes_hit() {
+    let shapes = moving_boxes(2000);
+    let tree = MotionKDTree::build(&shapes);
+    for time in [0., 0.5, 1.] {
+        let mut nb_hits = 0;
+        for (origin, direction) in rays(2000, 42) {
+            let result = tree.intersect(&origin, &direction, time);
+            for (index, shape) in shapes.iter().enumerate() {
+                if hits(&bound_at(shape, time), &origin, &direction) {
+                    nb_hits += 1;
+                    assert!(result.contains(&index), "shape {index} missed at {time}");
+                }
+            }
+        }
+        assert!(nb_hits > 100);
+    }
+}
+
+#[test]
+fn fast_shapes_are_only_returned_near_them_in_time() {
+    // Static boxes along x split the tree, the last box crosses the scene above them
+    let mut shapes: Vec<Moving> = (0..64)
+        .map(|x| {
+            let bb = AABB::new(
+                Vector3::new(x as f32, 0., 0.),
+                Vector3::new(x as f32 + 0.5, 1., 1.),
+            );
+            Moving(bb.clone(), bb)
+        })
+        .collect();
+    shapes.push(Moving(
+        AABB::new(Vector3::new(0., 10., 0.), Vector3::new(0.5, 11., 1.)),
+        AABB::new(Vector3::new(63., 10., 0.), Vector3::new(63.5, 11., 1.)),
+    ));
+    let tree = MotionKDTree::build(&shapes);
+
+    let origin = Vector3::new(63.25, 10.5, -1.);
+    let direction = Vector3::new(0., 0., 1.);
+    assert!(!tree.intersect(&origin, &direction, 0.).contains(&64));
+    assert!(tree.intersect(&origin, &direction, 1.).contains(&64));
+}
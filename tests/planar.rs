@@ -0,0 +1,133 @@
+//! Regression tests for scenes made of axis-aligned flat geometry (quads, points, walls).
+
+mod common;
+
+use cgmath::Vector3;
+use common::{Rng, hits};
+use kdtree_ray::{AABB, Bounded, KDTree};
+
+fn shape(min: [f32; 3], max: [f32; 3]) -> AABB {
+    AABB::new(min.into(), max.into())
+}
+
+/// Cast random rays through the scene and check that every shape hit is returned.
+fn check_rays(shapes: &[AABB], tree: &KDTree, rng: &mut Rng) {
+    let space = tree.bound();
+    let size = space.max - space.min;
+    for _ in 0..500 {
+        let target = Vector3::new(
+            space.min.x + rng.next() * size.x,
+            space.min.y + rng.next() * size.y,
+            space.min.z + rng.next() * size.z,
+        );
+        let direction = Vector3::new(rng.next() - 0.5, rng.next() - 0.5, rng.next() - 0.5);
+        let origin = target - direction * 10.;
+        let result = tree.intersect(&origin, &direction);
+        for (index, s) in shapes.iter().enumerate() {
+            if hits(s, &origin, &direction) {
+                assert!(
+                    result.contains(&index),
+                    "shape {index} is hit but not returned"
+                );
+            }
+        }
+    }
+}
+
+/// Build the tree, check its structure and its queries.
+fn check_scene(shapes: &[AABB]) -> KDTree {
+    let tree = KDTree::build(shapes);
+    assert_eq!(tree.validate(shapes), Ok(()));
+    check_rays(shapes, &tree, &mut Rng(42));
+    tree
+}
+
+#[test]
+fn coplanar_quads() {
+    // A 32x32 grid of quads all lying in the plane z = 0
+    let shapes: Vec<AABB> = (0..32 * 32)
+        .map(|i| {
+            let (x, y) = ((i % 32) as f32, (i / 32) as f32);
+            shape([x, y, 0.], [x + 0.9, y + 0.9, 0.])
+        })
+        .collect();
+    let tree = check_scene(&shapes);
+    let stats = tree.stats();
+    assert!(stats.max_depth > 5);
+    assert!(stats.duplication_factor < 2.);
+}
+
+#[test]
+fn stacked_quads() {
+    // Floors of quads lying in the planes z = 0, 1, 2, ...
+    let shapes: Vec<AABB> = (0..16 * 64)
+        .map(|i| {
+            let (x, z) = ((i % 16) as f32, (i / 16) as f32);
+            shape([x, 0., z], [x + 1., 8., z])
+        })
+        .collect();
+    let tree = check_scene(&shapes);
+
+    // Quads lying in splitting planes are not duplicated
+    assert_eq!(tree.stats().duplication_factor, 1.);
+
+    // A vertical ray only crosses the quads of its column
+    let result = tree.intersect(&Vector3::new(3.5, 4., -1.), &Vector3::new(0., 0., 1.));
+    assert!(result.len() < shapes.len() / 4);
+    for z in 0..64 {
+        assert!(result.contains(&(z * 16 + 3)));
+    }
+}
+
+#[test]
+fn points() {
+    let mut rng = Rng(7);
+    let shapes: Vec<AABB> = (0..2000)
+        .map(|_| {
+            let p = [rng.next() * 10., rng.next() * 10., rng.next() * 10.];
+            shape(p, p)
+        })
+        .collect();
+    let tree = check_scene(&shapes);
+    assert!(tree.stats().max_depth > 5);
+}
+
+#[test]
+fn duplicated_points() {
+    // Many shapes at the exact same positions
+    let shapes: Vec<AABB> = (0..1000)
+        .map(|i| {
+            let p = [(i % 10) as f32, 0., 0.];
+            shape(p, p)
+        })
+        .collect();
+    let tree = check_scene(&shapes);
+
+    // The line of points is split until leaves only contain points at the two borders of
+    // their space
+    assert!(tree.stats().leaf_size_histogram.len() <= 201);
+}
+
+#[test]
+fn room_walls() {
+    // Walls of a grid of rooms: quads flat on X or Y, and axis-aligned wall triangles
+    // (flat on X or Y) sharing the planes of the walls.
+    let mut shapes = vec![];
+    for i in 0..=16 {
+        let p = i as f32 * 4.;
+        for j in 0..16 {
+            let q = j as f32 * 4.;
+            shapes.push(shape([p, q, 0.], [p, q + 4., 3.]));
+            shapes.push(shape([q, p, 0.], [q + 4., p, 3.]));
+            shapes.push(shape([p, q + 1., 0.], [p, q + 2., 1.]));
+            shapes.push(shape([q + 1., p, 2.], [q + 3., p, 3.]));
+        }
+    }
+    // Floor and ceiling
+    shapes.push(shape([0., 0., 0.], [64., 64., 0.]));
+    shapes.push(shape([0., 0., 3.], [64., 64., 3.]));
+    let tree = check_scene(&shapes);
+    let stats = tree.stats();
+    assert!(stats.max_depth > 5);
+    assert!(stats.duplication_factor < 2.5);
+}
@@ -0,0 +1,133 @@
+//! Builds report their progress and can be cancelled or stopped at a deadline.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use cgmath::Vector3;
+use kdtree_ray::{AABB, BuildError, BuilderConfig, CancellationToken, KDTree};
+
+/// A grid of small boxes.
+fn scene() -> Vec<AABB> {
+    (0..20 * 20 * 20)
+        .map(|i| {
+            let min = Vector3::new((i % 20) as f32, (i / 20 % 20) as f32, (i / 400) as f32);
+            AABB::new(min, min + Vector3::new(0.5, 0.5, 0.5))
+        })
+        .collect()
+}
+
+#[test]
+fn progress_is_reported_up_to_the_end() {
+    let shapes = scene();
+    let reports = Arc::new(Mutex::new(vec![]));
+    let config = {
+        let reports = reports.clone();
+        BuilderConfig::default()
+            .with_progress(move |progress| reports.lock().unwrap().push(progress))
+    };
+    let tree = KDTree::build_config(&shapes, &config);
+    assert!(tree == KDTree::build(&shapes));
+
+    let reports = reports.lock().unwrap();
+    assert!(reports.len() > 10);
+    assert!(
+        reports
+            .iter()
+            .all(|&progress| (0. ..=1.).contains(&progress))
+    );
+    assert_eq!(reports.last(), Some(&1.));
+}
+
+#[test]
+fn built_trees_dont_keep_the_progress_callback() {
+    let shapes = scene();
+    let reports = Arc::new(Mutex::new(vec![]));
+    let config = {
+        let reports = reports.clone();
+        BuilderConfig::default()
+            .with_progress(move |progress| reports.lock().unwrap().push(progress))
+    };
+    let mut tree = KDTree::build_config(&shapes, &config);
+    drop(config);
+    assert_eq!(Arc::strong_count(&reports), 1);
+
+    // Edits don't report their progress either
+    let nb_reports = reports.lock().unwrap().len();
+    let min = Vector3::new(5.25, 5.25, 5.25);
+    tree.insert(
+        shapes.len(),
+        &AABB::new(min, min + Vector3::new(0.5, 0.5, 0.5)),
+    );
+    assert_eq!(reports.lock().unwrap().len(), nb_reports);
+}
+
+#[test]
+fn cancelled_builds_return_an_error() {
+    let shapes = scene();
+    let token = CancellationToken::new();
+    token.cancel();
+    let config = BuilderConfig::default().with_cancellation(token);
+    let result = KDTree::try_build_config(&shapes, &config);
+    assert_eq!(result.err(), Some(BuildError::Cancelled));
+}
+
+#[test]
+fn builds_can_be_cancelled_while_running() {
+    let shapes = scene();
+    let token = CancellationToken::new();
+    let config = {
+        let token = token.clone();
+        BuilderConfig::default()
+            .with_cancellation(token.clone())
+            .with_progress(move |progress| {
+                if progress > 0.3 {
+                    token.cancel()
+                }
+            })
+    };
+    let result = KDTree::try_build_config(&shapes, &config);
+    assert_eq!(result.err(), Some(BuildError::Cancelled));
+    assert!(token.is_cancelled());
+
+    // A new token is needed to build again
+    let config = config.with_cancellation(CancellationToken::new());
+    assert!(KDTree::try_build_config(&shapes, &config).is_ok());
+}
+
+#[test]
+fn deadline_builds_always_give_valid_trees() {
+    let shapes = scene();
+    let config = BuilderConfig::default();
+
+    // Enough time for a full build
+    let deadline = Instant::now() + Duration::from_secs(3600);
+    let (tree, report) = KDTree::build_with_deadline(&shapes, &config, deadline);
+    assert!(report.is_complete());
+    assert!(report.completed > 0.999);
+    assert!(tree == KDTree::build_config(&shapes, &config));
+
+    // No time at all, the root is a leaf
+    let (tree, report) = KDTree::build_with_deadline(&shapes, &config, Instant::now());
+    assert_eq!(report.nb_unfinished_nodes, 1);
+    assert_eq!(report.completed, 0.);
+    assert_eq!(tree.validate(&shapes), Ok(()));
+    assert_eq!(tree.stats().nb_leaves, 1);
+
+    // Stopped in the middle of the build
+    let token = CancellationToken::new();
+    let config = {
+        let token = token.clone();
+        config
+            .with_cancellation(token.clone())
+            .with_progress(move |progress| {
+                if progress > 0.3 {
+                    token.cancel()
+                }
+            })
+    };
+    let (tree, report) = KDTree::build_with_deadline(&shapes, &config, deadline);
+    assert!(!report.is_complete());
+    assert!(report.completed > 0.3 && report.completed < 1.);
+    assert_eq!(tree.validate(&shapes), Ok(()));
+    assert!(tree.stats().nb_leaves > 1);
+}
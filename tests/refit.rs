@@ -0,0 +1,110 @@
+//! Refitted trees must keep returning every shape a ray can hit, and report when they can't.
+
+mod common;
+
+use cgmath::Vector3;
+use common::{brute_force, random_boxes, rays, row_of_boxes};
+use kdtree_ray::{AABB, KDTree};
+
+/// Check that the tree returns every box hit by random rays.
+fn check_rays(tree: &KDTree, boxes: &[AABB]) {
+    for (origin, direction) in rays(500, 3) {
+        let result = tree.intersect(&origin, &direction);
+        for index in brute_force(boxes, &origin, &direction) {
+            assert!(
+                result.contains(&index),
+                "box {index} is hit but not returned"
+            );
+        }
+    }
+}
+
+#[test]
+fn shapes_moving_inside_their_leaves_keep_the_tree_valid() {
+    let mut boxes = random_boxes(2000, 9);
+    let mut tree = KDTree::build(&boxes);
+    let reference = tree.clone();
+
+    // Refitting the same shapes only tightens the spaces of the nodes
+    let report = tree.refit(&boxes);
+    assert!(report.is_valid());
+    assert!(!report.rebuild_recommended());
+    assert_eq!(report.nb_stale_references, 0);
+    assert_eq!(tree.validate(&boxes), Ok(()));
+    check_rays(&tree, &boxes);
+    for (origin, direction) in rays(500, 4) {
+        let result = tree.intersect(&origin, &direction);
+        let expected = reference.intersect(&origin, &direction);
+        assert!(result.iter().all(|index| expected.contains(index)));
+    }
+
+    // Shrink every box around its center
+    for bb in boxes.iter_mut() {
+        let margin = (bb.max - bb.min) * 0.1;
+        *bb = AABB::new(bb.min + margin, bb.max - margin);
+    }
+    let report = tree.refit(&boxes);
+    assert!(report.is_valid());
+    assert_eq!(report.nb_missing_references, 0);
+    assert!(report.sah_cost_after <= report.sah_cost_before);
+    assert_eq!(tree.validate(&boxes), Ok(()));
+    check_rays(&tree, &boxes);
+}
+
+#[test]
+fn shapes_crossing_a_splitting_plane_make_the_tree_invalid() {
+    let mut boxes = row_of_boxes(64);
+    let mut tree = KDTree::build(&boxes);
+    boxes[3] = AABB::new(Vector3::new(40.2, 0., 0.), Vector3::new(40.8, 1., 1.));
+    let report = tree.refit(&boxes);
+    assert!(report.nb_missing_references > 0);
+    assert!(!report.is_valid());
+    assert!(report.rebuild_recommended());
+    assert!(tree.validate(&boxes).is_err());
+}
+
+#[test]
+fn shapes_flat_on_a_splitting_plane_or_the_root_boundary_make_the_tree_invalid() {
+    let boxes = row_of_boxes(64);
+    for x in [32., 64.] {
+        let mut tree = KDTree::build(&boxes);
+        let mut moved = boxes.clone();
+        moved[3] = AABB::new(Vector3::new(x, 0., 0.), Vector3::new(x, 1., 1.));
+        let report = tree.refit(&moved);
+        assert!(report.nb_missing_references > 0, "quad at x = {x}");
+        assert!(!report.is_valid());
+        assert!(tree.validate(&moved).is_err());
+    }
+}
+
+#[test]
+#[should_panic(expected = "refit needs the shapes the tree was built with")]
+fn refitting_fewer_shapes_panics() {
+    let boxes = row_of_boxes(64);
+    KDTree::build(&boxes).refit(&boxes[..10]);
+}
+
+#[test]
+fn shapes_moving_back_are_found_again() {
+    let boxes = row_of_boxes(64);
+    let mut moved = boxes.clone();
+    moved[3] = AABB::new(Vector3::new(40.2, 0., 0.), Vector3::new(40.8, 1., 1.));
+    let mut tree = KDTree::build(&boxes);
+    assert!(!tree.refit(&moved).is_valid());
+
+    // Stale references are kept, so the tree is valid again once the shape is back
+    let report = tree.refit(&boxes);
+    assert!(report.is_valid());
+    assert_eq!(tree.validate(&boxes), Ok(()));
+    let origin = Vector3::new(3.2, -1., 0.5);
+    let direction = Vector3::new(0., 1., 0.);
+    assert_eq!(tree.intersect(&origin, &direction), vec![3]);
+
+    // Removing a moved shape also drops its stale references
+    tree.refit(&moved);
+    assert!(tree.remove(3));
+    for x in 0..64 {
+        let origin = Vector3::new(x as f32 + 0.5, -1., 0.5);
+        assert!(!tree.intersect(&origin, &direction).contains(&3));
+    }
+}
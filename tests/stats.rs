@@ -0,0 +1,82 @@
+//! Tree statistics and query counters must match the shape of the tree.
+
+mod common;
+
+use cgmath::Vector3;
+use common::{TreeFile, row_of_boxes};
+use kdtree_ray::{KDTree, QueryStats};
+
+#[test]
+fn query_stats_count_the_nodes_visited() {
+    let shapes = row_of_boxes(16);
+    let tree = KDTree::build(&shapes);
+    let tree_stats = tree.stats();
+    assert!(tree_stats.nb_nodes > 1);
+    let nb_inner_nodes = tree_stats.nb_nodes - tree_stats.nb_leaves;
+
+    // A ray along the row enters the space of every node
+    let origin = Vector3::new(-1., 0.5, 0.5);
+    let direction = Vector3::new(1., 0., 0.);
+    let mut stats = QueryStats::default();
+    let result = tree.intersect_with_stats(&origin, &direction, &mut stats);
+    assert_eq!(result, (0..shapes.len()).collect::<Vec<_>>());
+    assert_eq!(
+        stats,
+        QueryStats {
+            nb_nodes_visited: tree_stats.nb_nodes,
+            nb_aabb_tests: 2 * nb_inner_nodes,
+            nb_leaves_visited: tree_stats.nb_leaves,
+            nb_references: tree_stats.nb_references,
+        }
+    );
+
+    // A ray missing the row only tests the children of the root, counters accumulate
+    let origin = Vector3::new(-1., 5., 0.5);
+    let result = tree.intersect_with_stats(&origin, &direction, &mut stats);
+    assert!(result.is_empty());
+    assert_eq!(stats.nb_nodes_visited, tree_stats.nb_nodes + 1);
+    assert_eq!(stats.nb_aabb_tests, 2 * nb_inner_nodes + 2);
+    assert_eq!(stats.nb_leaves_visited, tree_stats.nb_leaves);
+
+    // Counting doesn't change the result
+    assert_eq!(
+        tree.intersect(&origin, &direction),
+        tree.intersect_with_stats(&origin, &direction, &mut QueryStats::default())
+    );
+}
+
+#[test]
+fn tree_stats_count_the_nodes_and_references() {
+    // A node with two leaves on the left and an empty leaf on the right
+    let file = TreeFile::new()
+        .leaf(&[0])
+        .leaf(&[0, 1])
+        .node(0, 1)
+        .leaf(&[])
+        .node(2, 3)
+        .bounds(2)
+        .footer(4);
+    let tree = KDTree::<f32>::read_from(&mut file.as_slice()).unwrap();
+    let stats = tree.stats();
+    assert_eq!(stats.nb_nodes, 5);
+    assert_eq!(stats.nb_leaves, 3);
+    assert_eq!(stats.nb_empty_leaves, 1);
+    assert_eq!(stats.max_depth, 2);
+    assert_eq!(stats.avg_depth, 5. / 3.);
+    assert_eq!(stats.leaf_size_histogram, vec![1, 1, 1]);
+    assert_eq!(stats.nb_references, 3);
+    assert_eq!(stats.nb_unique_shapes, 2);
+    assert_eq!(stats.duplication_factor, 1.5);
+    assert!(stats.memory > 0);
+    assert_eq!(stats.sah_cost, tree.sah_cost());
+
+    // A single leaf is at depth 0
+    let shapes = row_of_boxes(1);
+    let stats = KDTree::build(&shapes).stats();
+    assert_eq!(
+        (stats.nb_nodes, stats.nb_leaves, stats.max_depth),
+        (1, 1, 0)
+    );
+    assert_eq!(stats.avg_depth, 0.);
+    assert_eq!(stats.leaf_size_histogram, vec![0, 1]);
+}
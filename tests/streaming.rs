@@ -0,0 +1,139 @@
+//! Trees written to disk and built out-of-core must be the same as the ones built in memory.
+
+use std::io;
+
+mod common;
+
+use cgmath::Vector2;
+use common::{Rng, TreeFile, aligned_boxes};
+use kdtree_ray::{AABB, BuilderConfig, D2, KDTree, KDTree2, StreamingBuilder};
+
+#[test]
+fn written_trees_are_read_back() {
+    let shapes = aligned_boxes(2000, 3);
+    let tree = KDTree::build(&shapes);
+    let mut file = vec![];
+    tree.write_to(&mut file).unwrap();
+    let read: KDTree = KDTree::read_from(&mut file.as_slice()).unwrap();
+    assert!(read == tree);
+    assert_eq!(read.fingerprint(), tree.fingerprint());
+    assert_eq!(read.stats().max_depth, tree.stats().max_depth);
+
+    // The float type and the dimension must match
+    let err = KDTree::<f64>::read_from(&mut file.as_slice()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let err = KDTree2::<f32>::read_from(&mut file.as_slice()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let err = KDTree::<f32>::read_from(&mut &file[..file.len() - 1]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn corrupted_trees_are_rejected() {
+    let read = |file: Vec<u8>| KDTree::<f32>::read_from(&mut file.as_slice());
+    let tree = read(
+        TreeFile::new()
+            .leaf(&[0])
+            .leaf(&[])
+            .node(0, 1)
+            .bounds(1)
+            .footer(2),
+    )
+    .unwrap();
+    assert_eq!(tree.stats().max_depth, 1);
+
+    // Shared child
+    let file = TreeFile::new().leaf(&[0]).node(0, 0).bounds(1).footer(1);
+    assert_eq!(read(file).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    // Cycle through the root
+    let file = TreeFile::new()
+        .leaf(&[0])
+        .node(0, 2)
+        .node(1, 0)
+        .bounds(1)
+        .footer(1);
+    assert_eq!(read(file).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    // Cycle out of reach of the root
+    let file = TreeFile::new()
+        .leaf(&[0])
+        .leaf(&[])
+        .node(0, 1)
+        .node(4, 5)
+        .node(3, 6)
+        .leaf(&[])
+        .leaf(&[])
+        .bounds(1)
+        .footer(2);
+    assert_eq!(read(file).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    // Duplicated bounds
+    let file = TreeFile::new().leaf(&[0]).bounds(1).bounds(1).footer(0);
+    assert_eq!(read(file).unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn deep_trees_are_read_back() {
+    // Each node has an empty leaf on the left and the rest of the tree on the right
+    let depth = 100_000;
+    let mut file = TreeFile::new().leaf(&[0]);
+    for i in 0..depth {
+        file = file.leaf(&[]).node(2 * i + 1, 2 * i);
+    }
+    let tree = KDTree::<f32>::read_from(&mut file.bounds(1).footer(2 * depth).as_slice()).unwrap();
+    assert_eq!(tree.stats().max_depth, depth as usize);
+}
+
+#[test]
+fn streamed_builds_are_equal_to_in_memory_builds() {
+    let shapes = aligned_boxes(5000, 3);
+    let config = BuilderConfig::default();
+    let reference = KDTree::build_config(&shapes, &config);
+
+    // From the whole build in memory to most of the nodes split on disk
+    for memory_budget in [1 << 30, 1 << 16, 1 << 10] {
+        let mut file = vec![];
+        StreamingBuilder::new(config.clone())
+            .with_memory_budget(memory_budget)
+            .build(shapes.iter().cloned(), &mut file)
+            .unwrap();
+        let tree: KDTree = KDTree::read_from(&mut file.as_slice()).unwrap();
+        assert!(tree == reference);
+        assert_eq!(tree.validate(&shapes), Ok(()));
+    }
+}
+
+#[test]
+fn bounds_are_streamed_from_a_reader() {
+    let mut rng = Rng(5);
+    let shapes: Vec<AABB<f64, D2>> = (0..1000)
+        .map(|_| {
+            let min = Vector2::new(rng.next() as f64 * 10., rng.next() as f64 * 10.);
+            AABB::new(min, min + Vector2::new(rng.next() as f64, 0.))
+        })
+        .collect();
+    let reference = KDTree2::build(&shapes);
+
+    // Boxes are written as their minimum and maximum coordinates
+    let mut input = vec![];
+    for bb in &shapes {
+        for value in [bb.min.x, bb.min.y, bb.max.x, bb.max.y] {
+            input.extend(value.to_le_bytes());
+        }
+    }
+    let mut file = vec![];
+    StreamingBuilder::<f64, D2>::default()
+        .with_memory_budget(1 << 12)
+        .build_from_reader(&mut input.as_slice(), &mut file)
+        .unwrap();
+    let tree = KDTree2::read_from(&mut file.as_slice()).unwrap();
+    assert!(tree == reference);
+
+    // Truncated boxes and empty scenes are errors
+    let err = StreamingBuilder::<f64, D2>::default()
+        .build_from_reader(&mut &input[..input.len() - 1], &mut vec![])
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    let err = StreamingBuilder::<f64, D2>::default()
+        .build(vec![], &mut vec![])
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,99 @@
+//! Triangle meshes must find the same closest hit as testing every triangle.
+
+mod common;
+
+use cgmath::{InnerSpace, Vector3};
+use common::{Rng, rays};
+use kdtree_ray::{KDTree, Triangle, TriangleHit, TriangleMesh};
+
+fn triangles(nb_triangles: usize) -> Vec<Triangle> {
+    let mut rng = Rng(5);
+    (0..nb_triangles)
+        .map(|_| {
+            let a = rng.vector(100.);
+            Triangle::new(&a, &(a + rng.vector(3.)), &(a + rng.vector(3.)))
+        })
+        .collect()
+}
+
+/// Möller–Trumbore intersection of a single triangle, returning the distance.
+fn intersect(triangle: &Triangle, origin: Vector3<f32>, direction: Vector3<f32>) -> Option<f32> {
+    let edge1 = triangle.b - triangle.a;
+    let edge2 = triangle.c - triangle.a;
+    let p = direction.cross(edge2);
+    let det = edge1.dot(p);
+    if det == 0. {
+        return None;
+    }
+    let s = origin - triangle.a;
+    let u = s.dot(p) / det;
+    let q = s.cross(edge1);
+    let v = direction.dot(q) / det;
+    let t = edge2.dot(q) / det;
+    (u >= 0. && v >= 0. && u + v <= 1. && t > 0.).then_some(t)
+}
+
+fn brute_force(
+    triangles: &[Triangle],
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+) -> Option<(usize, f32)> {
+    triangles
+        .iter()
+        .enumerate()
+        .filter_map(|(index, triangle)| Some((index, intersect(triangle, origin, direction)?)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+fn check<const W: usize>(triangles: &[Triangle]) {
+    let mesh: TriangleMesh<f32, W> = TriangleMesh::build(triangles.to_vec());
+    let mut nb_hits = 0;
+    for (origin, direction) in rays(2000, 17) {
+        let hit = mesh.intersect(&origin, &direction);
+        match (hit, brute_force(triangles, origin, direction)) {
+            (None, None) => {}
+            (
+                Some(TriangleHit {
+                    index,
+                    distance,
+                    u,
+                    v,
+                }),
+                Some((expected, expected_distance)),
+            ) => {
+                nb_hits += 1;
+                assert!((distance - expected_distance).abs() <= 1e-3 * expected_distance);
+                if index == expected {
+                    let triangle = &triangles[index];
+                    let point = triangle.a * (1. - u - v) + triangle.b * u + triangle.c * v;
+                    assert!((point - (origin + direction * distance)).magnitude() < 1e-2);
+                }
+            }
+            (hit, expected) => panic!("hit {hit:?} instead of {expected:?}"),
+        }
+    }
+    assert!(nb_hits > 100);
+}
+
+#[test]
+fn closest_hits_are_the_same_as_brute_force() {
+    let triangles = triangles(5000);
+    check::<1>(&triangles);
+    check::<4>(&triangles);
+    check::<8>(&triangles);
+}
+
+#[test]
+fn leaves_give_the_indices_of_their_triangles() {
+    let triangles = triangles(1000);
+    let mesh: TriangleMesh<f32, 4> = TriangleMesh::build(triangles.clone());
+    let tree = KDTree::build(&triangles);
+    for (origin, direction) in rays(200, 19) {
+        let mut shapes = vec![];
+        mesh.tree()
+            .intersect_leaves(&origin, &direction, |leaf| shapes.extend(leaf.shapes()));
+        shapes.sort_unstable();
+        shapes.dedup();
+        assert_eq!(shapes, tree.intersect(&origin, &direction));
+    }
+}
@@ -0,0 +1,130 @@
+//! Validation must report each invariant broken by a malformed tree or moved shapes.
+
+mod common;
+
+use std::io;
+
+use cgmath::Vector3;
+use common::{TreeFile, row_of_boxes};
+use kdtree_ray::{AABB, KDTree, ValidationError};
+
+/// Validation error wrapped by the error of `KDTree::read_from`.
+fn read_error(file: Vec<u8>) -> ValidationError {
+    let err = KDTree::<f32>::read_from(&mut file.as_slice()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    *err.into_inner().unwrap().downcast().unwrap()
+}
+
+fn moved(shapes: &[AABB], index: usize, min: [f32; 3], max: [f32; 3]) -> Vec<AABB> {
+    let mut shapes = shapes.to_vec();
+    shapes[index] = AABB::new(min.into(), max.into());
+    shapes
+}
+
+#[test]
+fn malformed_trees_are_reported() {
+    assert_eq!(
+        read_error(TreeFile::new().bounds(1).footer(0)),
+        ValidationError::EmptyTree
+    );
+    assert_eq!(
+        read_error(TreeFile::new().leaf(&[0]).node(0, 5).bounds(1).footer(1)),
+        ValidationError::ChildOutOfRange { node: 1, child: 5 }
+    );
+    assert_eq!(
+        read_error(TreeFile::new().leaf(&[0]).node(0, 0).bounds(1).footer(1)),
+        ValidationError::NotATree { node: 0 }
+    );
+    assert_eq!(
+        read_error(
+            TreeFile::new()
+                .leaf(&[0])
+                .node(0, 2)
+                .node(3, 1)
+                .leaf(&[])
+                .bounds(1)
+                .footer(1)
+        ),
+        ValidationError::NotATree { node: 1 }
+    );
+    let file = TreeFile::new()
+        .leaf(&[0])
+        .leaf(&[])
+        .node(0, 1)
+        .leaf(&[])
+        .bounds(1)
+        .footer(2);
+    assert_eq!(
+        read_error(file),
+        ValidationError::UnreachableNode { node: 3 }
+    );
+    assert_eq!(
+        read_error(TreeFile::new().leaf(&[3]).bounds(1).footer(0)),
+        ValidationError::ShapeOutOfRange { leaf: 0, shape: 3 }
+    );
+
+    // Spaces aren't checked when reading a tree
+    let file = TreeFile::new()
+        .leaf(&[0])
+        .leaf(&[])
+        .node(0, 1)
+        .bounds(1)
+        .footer_with_space(2, [0., 0., 0.], [1., 0.5, 1.]);
+    let tree: KDTree = KDTree::read_from(&mut file.as_slice()).unwrap();
+    let shape = AABB::new(Vector3::new(0., 0., 0.), Vector3::new(0.5, 0.5, 1.));
+    assert_eq!(
+        tree.validate(&[shape]),
+        Err(ValidationError::ChildOutsideParent { node: 0, child: 1 })
+    );
+
+    // Leaves whose spaces don't cover their shapes
+    let file = TreeFile::new()
+        .leaf(&[0])
+        .leaf(&[0])
+        .node_with_spaces(0, 1, [1., 0.5, 1.])
+        .bounds(1)
+        .footer(2);
+    let tree: KDTree = KDTree::read_from(&mut file.as_slice()).unwrap();
+    let shape = AABB::new(Vector3::new(0., 0., 0.), Vector3::new(1., 1., 1.));
+    assert_eq!(
+        tree.validate(&[shape]),
+        Err(ValidationError::UncoveredShape { leaf: 1, shape: 0 })
+    );
+}
+
+#[test]
+fn moved_shapes_are_reported() {
+    let shapes = row_of_boxes(64);
+    let tree = KDTree::build(&shapes);
+    assert_eq!(tree.validate(&shapes), Ok(()));
+
+    assert!(matches!(
+        tree.validate(&shapes[..10]),
+        Err(ValidationError::ShapeOutOfRange { .. })
+    ));
+    assert_eq!(
+        tree.validate(&moved(&shapes, 3, [3., 5., 0.], [4., 6., 1.])),
+        Err(ValidationError::ShapeOutsideRoot { shape: 3 })
+    );
+    assert!(matches!(
+        tree.validate(&moved(&shapes, 3, [40.2, 0., 0.], [40.8, 1., 1.])),
+        Err(ValidationError::MissingShape { shape: 3, .. })
+    ));
+
+    // Shapes flat on the boundary of the root or on a splitting plane
+    for x in [0., 32., 64.] {
+        assert!(matches!(
+            tree.validate(&moved(&shapes, 3, [x, 0., 0.], [x, 1., 1.])),
+            Err(ValidationError::MissingShape { shape: 3, .. })
+        ));
+        assert!(matches!(
+            tree.validate(&moved(&shapes, 3, [x, 0.5, 0.5], [x, 0.5, 0.5])),
+            Err(ValidationError::MissingShape { shape: 3, .. })
+        ));
+    }
+    // A shape flat on the plane it was split by
+    assert_eq!(
+        tree.validate(&moved(&shapes, 3, [3., 0., 0.], [3., 1., 1.])),
+        Ok(())
+    );
+}